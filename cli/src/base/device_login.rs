@@ -0,0 +1,128 @@
+use anyhow::{bail, Context, Result};
+use movey_client::oauth::{DeviceAuthorization, DeviceTokenPoll, TokenResponse};
+use movey_client::{MoveyApiError, MoveyClient};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::movey_client;
+
+/// How much the interval between polls grows when the registry asks to
+/// slow down.
+pub const SLOW_DOWN_INCREMENT: Duration = Duration::from_secs(5);
+
+/// Get a token by authorizing this machine from another one: the user enters
+/// a code on the registry while the CLI polls for the token.
+pub fn device_login(movey_url: &str) -> Result<TokenResponse> {
+    let client = movey_client(movey_url);
+    let authorization = client
+        .request_device_code()
+        .context("Could not start the device login")?;
+
+    println!(
+        "Open {} in a browser and enter the code {}",
+        authorization.verification_uri, authorization.user_code
+    );
+    if let Some(uri) = &authorization.verification_uri_complete {
+        println!("or open {}", uri);
+    }
+    println!("Waiting for the authorization...");
+    wait_for_token(&client, &authorization, &mut thread::sleep)
+}
+
+// poll at the interval asked by the registry, waiting with `sleep`, until
+// the user answers or the code expires
+fn wait_for_token(
+    client: &MoveyClient,
+    authorization: &DeviceAuthorization,
+    sleep: &mut dyn FnMut(Duration),
+) -> Result<TokenResponse> {
+    let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+    let mut interval = Duration::from_secs(authorization.interval);
+    loop {
+        sleep(interval);
+        if Instant::now() >= deadline {
+            bail!("The code expired before it was entered, login again")
+        }
+        match client.poll_device_token(&authorization.device_code) {
+            Ok(DeviceTokenPoll::Token(response)) => return Ok(response),
+            Ok(DeviceTokenPoll::Pending) => {}
+            Ok(DeviceTokenPoll::SlowDown) => interval += SLOW_DOWN_INCREMENT,
+            Err(MoveyApiError::Rejected { message, .. }) if message == "access_denied" => {
+                bail!("The authorization was denied")
+            }
+            Err(MoveyApiError::Rejected { message, .. }) if message == "expired_token" => {
+                bail!("The code expired before it was entered, login again")
+            }
+            Err(error) => {
+                return Err(anyhow::Error::new(error)
+                    .context("Could not get a token for the authorization"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::{Method::POST, Mock, MockServer};
+    use serde_json::json;
+
+    // the registry answers the polls with the token, or with `error`
+    fn mock_poll<'a>(server: &'a MockServer, error: &str) -> Mock<'a> {
+        server.mock(|when, then| {
+            when.method(POST).path("/api/v1/oauth/token");
+            match error {
+                "" => then
+                    .status(200)
+                    .json_body(json!({ "token": "device-token" })),
+                _ => then.status(400).json_body(json!({ "error": error })),
+            };
+        })
+    }
+
+    fn authorization(expires_in: u64) -> DeviceAuthorization {
+        DeviceAuthorization {
+            device_code: String::from("device-code"),
+            user_code: String::from("WDJB-MJHT"),
+            verification_uri: String::from("https://www.movey.net/device"),
+            verification_uri_complete: None,
+            expires_in,
+            interval: 1,
+        }
+    }
+
+    #[test]
+    fn wait_for_token_respects_the_interval_and_slow_down() {
+        let server = MockServer::start();
+        let client = MoveyClient::new(&server.base_url());
+        let answers = [
+            "authorization_pending",
+            "slow_down",
+            "authorization_pending",
+            "",
+        ];
+        let mut mock: Option<Mock> = None;
+        let mut sleeps = vec![];
+        let response = wait_for_token(&client, &authorization(900), &mut |interval| {
+            // the answer to the poll following this sleep
+            if let Some(mut mock) = mock.take() {
+                mock.delete();
+            }
+            mock = Some(mock_poll(&server, answers[sleeps.len()]));
+            sleeps.push(interval);
+        })
+        .unwrap();
+        assert_eq!(response.token, "device-token");
+        assert_eq!(sleeps, [1, 1, 6, 6].map(Duration::from_secs));
+
+        mock.unwrap().delete();
+        mock_poll(&server, "access_denied");
+        let error = wait_for_token(&client, &authorization(900), &mut |_| {}).unwrap_err();
+        assert_eq!(error.to_string(), "The authorization was denied");
+
+        let error = wait_for_token(&client, &authorization(0), &mut |_| {}).unwrap_err();
+        assert!(error.to_string().contains("expired"));
+    }
+}
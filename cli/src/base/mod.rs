@@ -1,2 +1,166 @@
+pub mod device_login;
+pub mod movey_config;
 pub mod movey_login;
+pub mod movey_logout;
+pub mod movey_package;
+pub mod movey_token;
 pub mod movey_upload;
+pub mod movey_whoami;
+pub mod web_login;
+
+use anyhow::{bail, Result};
+use movey_client::{oauth::TokenResponse, ClientEvent, MoveyClient};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use utils::credential_store::{env_passphrase, Credential};
+use utils::env::MOVE_HOME;
+use utils::movey_credential::{
+    get_registry_credential, resolve_movey_url, resolve_token_url, RegistryCredential, RegistryUrl,
+    DEFAULT_REGISTRY,
+};
+
+/// Client of the registry at `url`, identifying itself as this CLI and
+/// printing what it reports on stderr.
+pub fn movey_client(url: &str) -> MoveyClient {
+    MoveyClient::new(url)
+        .with_user_agent(format!("movey-cli/{}", env!("CARGO_PKG_VERSION")))
+        .on_event(|event| match event {
+            ClientEvent::TokenExpiring { .. } => eprintln!("Warning: {}", event),
+            ClientEvent::Retrying { .. } => eprintln!("{}", event),
+        })
+}
+
+/// Like [`movey_client`], sending the token of `registry_credential`. A
+/// token with a refresh token is refreshed when it expires, and the new one
+/// is saved in place of the old one.
+pub fn authenticated_client(url: &str, registry_credential: RegistryCredential) -> MoveyClient {
+    let RegistryCredential {
+        name,
+        credential,
+        store,
+    } = registry_credential;
+    let mut client = movey_client(url).with_token(credential.token);
+    if let Some(expires_at) = credential.expires_at {
+        client = client.with_token_expiry(UNIX_EPOCH + Duration::from_secs(expires_at));
+    }
+    if let (Some(refresh_token), Some(store)) = (credential.refresh_token, store) {
+        let scopes = credential.scopes;
+        client = client
+            .with_refresh_token(refresh_token)
+            .on_token_refresh(move |response| {
+                let mut refreshed = issued_credential(response.clone());
+                // the scopes stay the same unless the registry says otherwise
+                if refreshed.scopes.is_none() {
+                    refreshed.scopes = scopes.clone();
+                }
+                if let Err(error) = store.store(&name, &refreshed) {
+                    eprintln!(
+                        "Warning: the API token was refreshed but could not be saved: {:#}",
+                        error
+                    );
+                }
+            });
+    }
+    client
+}
+
+/// The credential of `registry` and the url of the registry, for the
+/// commands managing the account. `registry_url` overrides the configured
+/// url.
+pub fn registry_credential(
+    registry: Option<&str>,
+    registry_url: Option<&str>,
+) -> Result<(RegistryCredential, String)> {
+    let credential = get_registry_credential(&MOVE_HOME, registry, &credential_passphrase)?;
+    let url = credential_url(&credential, registry_url, &env::current_dir()?)?.url;
+    Ok((credential, url))
+}
+
+/// The url to send the token of `registry_credential` to, `flag` being the
+/// value of `--registry-url`. A saved token is never sent to the url of a
+/// project `Movey.toml`, see [`resolve_token_url`].
+pub fn credential_url(
+    registry_credential: &RegistryCredential,
+    flag: Option<&str>,
+    project_dir: &Path,
+) -> Result<RegistryUrl> {
+    let resolve = if registry_credential.store.is_some() {
+        resolve_token_url
+    } else {
+        resolve_movey_url
+    };
+    resolve(
+        &MOVE_HOME,
+        Some(&registry_credential.name),
+        flag,
+        Some(project_dir),
+    )
+}
+
+/// Refuse to run `movey {command}` with a token known to lack `scope`, so
+/// that it fails before doing any work rather than when the registry rejects
+/// it.
+pub fn require_scope(
+    registry_credential: &RegistryCredential,
+    scope: &str,
+    command: &str,
+) -> Result<()> {
+    let credential = &registry_credential.credential;
+    if !credential.lacks_scope(scope) {
+        return Ok(());
+    }
+    let scopes = match credential.scopes.as_deref() {
+        Some(scopes) if !scopes.is_empty() => format!("it only has `{}`", scopes.join("`, `")),
+        _ => String::from("it has no scope"),
+    };
+    bail!(
+        "The API token for {} lacks the `{}` scope needed by `movey {}`, {}. \
+        Login again with a token having it, created on the registry or with \
+        `movey token create --scopes {}`",
+        registry_display_name(&registry_credential.name),
+        scope,
+        command,
+        scopes,
+        scope
+    )
+}
+
+/// The credential of a token issued by the registry, which may expire.
+pub fn issued_credential(response: TokenResponse) -> Credential {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Credential {
+        expires_at: response.expires_in.map(|expires_in| now + expires_in),
+        scopes: response.scopes(),
+        token: response.token,
+        refresh_token: response.refresh_token,
+    }
+}
+
+/// Name of the registry `name` in messages.
+pub fn registry_display_name(name: &str) -> String {
+    if name == DEFAULT_REGISTRY {
+        String::from("Movey")
+    } else {
+        format!("registry `{}`", name)
+    }
+}
+
+/// Passphrase of the encrypted credential store, from
+/// `MOVEY_CREDENTIAL_PASSPHRASE` or asked for on the terminal.
+pub fn credential_passphrase() -> Result<String> {
+    if env_passphrase().is_ok() || !io::stdin().is_terminal() {
+        return env_passphrase();
+    }
+    let passphrase = rpassword::prompt_password("Passphrase of the credential store: ")?;
+    if passphrase.is_empty() {
+        bail!("No passphrase was entered")
+    }
+    Ok(passphrase)
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use utils::credential_store::{encrypted_store_path, is_encrypted_store, open_credential_store};
+use utils::env::MOVE_HOME;
+use utils::movey_credential::{
+    find_project_config, get_env_api_token, registry_token_env, resolve_movey_url,
+    MOVEY_CREDENTIAL_PATH,
+};
+
+use super::credential_passphrase;
+
+/// Show the registry used by the other commands, and where its url and
+/// token come from.
+#[derive(Parser)]
+#[clap(name = "config", about = "Show the Movey configuration")]
+pub struct MoveyConfig {
+    /// Name of the registry to show [default: registry.default or movey]
+    #[clap(long, value_name = "NAME")]
+    pub registry: Option<String>,
+    /// Url of the registry, instead of the configured one
+    #[clap(long, value_name = "URL")]
+    pub registry_url: Option<String>,
+}
+
+impl MoveyConfig {
+    pub fn execute(&self) -> Result<()> {
+        let project_dir = env::current_dir()?;
+        let registry_url = resolve_movey_url(
+            &MOVE_HOME,
+            self.registry.as_deref(),
+            self.registry_url.as_deref(),
+            Some(&project_dir),
+        )?;
+        let credential_path = format!("{}{}", *MOVE_HOME, MOVEY_CREDENTIAL_PATH);
+        let (stored_token, store) = if is_encrypted_store(&MOVE_HOME)? {
+            // the configuration is shown without asking for the passphrase,
+            // so the store is not decrypted to look for the token
+            let path = encrypted_store_path(&MOVE_HOME);
+            let stored_token = if path.exists() {
+                "unknown (encrypted store, not decrypted)"
+            } else {
+                "not set"
+            };
+            (
+                String::from(stored_token),
+                format!("encrypted file {}", path.display()),
+            )
+        } else {
+            let store = open_credential_store(&MOVE_HOME, &credential_passphrase)?;
+            let stored_token = match store.get(&registry_url.name)? {
+                Some(_) => format!("set (from {})", store.describe()),
+                None => String::from("not set"),
+            };
+            (stored_token, store.describe())
+        };
+        let token = if get_env_api_token(&registry_url.name).is_some() {
+            format!(
+                "set (from {} environment variable)",
+                registry_token_env(&registry_url.name)
+            )
+        } else {
+            stored_token
+        };
+        let project_config = match find_project_config(&project_dir) {
+            Some(path) => path.display().to_string(),
+            None => String::from("none"),
+        };
+
+        println!("registry: {}", registry_url.name);
+        println!("url: {} (from {})", registry_url.url, registry_url.source);
+        println!("token: {}", token);
+        println!("credential file: {}", credential_path);
+        println!("credential store: {}", store);
+        println!("project config: {}", project_config);
+        Ok(())
+    }
+}
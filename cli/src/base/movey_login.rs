@@ -1,33 +1,165 @@
 use anyhow::{bail, Result};
 use clap::Parser;
-use std::{fs, fs::File, io, path::PathBuf};
-use toml_edit::easy::{map::Map, Value};
+use movey_client::{MoveyApiError, User};
+use std::{
+    env,
+    io::{self, BufRead, IsTerminal},
+};
+use utils::credential_store::{env_passphrase, open_credential_store, Credential};
 use utils::env::MOVE_HOME;
-use utils::movey_credential::read_credential_file;
+use utils::movey_credential::{registry_name, resolve_token_url};
 
-#[cfg(debug_assertions)]
-pub const MOVEY_URL: &str = "https://movey-app-staging.herokuapp.com";
-#[cfg(not(debug_assertions))]
-pub const MOVEY_URL: &str = "https://www.movey.net";
-pub const MOVEY_CREDENTIAL_PATH: &str = "/movey_credential.toml";
+use super::device_login::device_login;
+use super::web_login::web_login;
+use super::{credential_passphrase, issued_credential, movey_client, registry_display_name};
 
+/// Save the API token used to upload packages to Movey.
 #[derive(Parser)]
-#[clap(name = "login")]
-pub struct MoveyLogin;
+#[clap(name = "login", about = "Login to Movey")]
+pub struct MoveyLogin {
+    /// Read the token from stdin instead of prompting for it
+    #[clap(long)]
+    pub token_stdin: bool,
+    /// Authorize the CLI in the browser instead of pasting a token
+    #[clap(long, conflicts_with = "token-stdin")]
+    pub web: bool,
+    /// Authorize this machine by entering a code on another one
+    #[clap(long, conflicts_with_all = &["token-stdin", "web"])]
+    pub device: bool,
+    /// Name of the registry to login to [default: registry.default or movey]
+    #[clap(long, value_name = "NAME")]
+    pub registry: Option<String>,
+    /// Url of the registry, instead of the configured one
+    #[clap(long, value_name = "URL")]
+    pub registry_url: Option<String>,
+}
+
+/// How `movey login` gets the token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStrategy {
+    /// The user pastes a token copied from the registry, or gives it on
+    /// stdin.
+    Paste,
+    /// The user authorizes the CLI in the browser, which comes back to a
+    /// server of the CLI.
+    Web,
+    /// The user enters a code on the registry from another machine, while
+    /// the CLI polls for the token.
+    Device,
+}
 
 impl MoveyLogin {
-    pub fn execute() -> Result<()> {
+    pub fn strategy(&self) -> LoginStrategy {
+        if self.web {
+            LoginStrategy::Web
+        } else if self.device {
+            LoginStrategy::Device
+        } else {
+            LoginStrategy::Paste
+        }
+    }
+
+    pub fn execute(&self) -> Result<()> {
+        let registry = self.registry.as_deref();
+        let project_dir = env::current_dir()?;
+        // the token is saved, so it must not go to the url of the project
+        let movey_url = resolve_token_url(
+            &MOVE_HOME,
+            registry,
+            self.registry_url.as_deref(),
+            Some(&project_dir),
+        )?
+        .url;
+        let mut credential = match self.strategy() {
+            LoginStrategy::Paste => Credential::new(self.paste_token(&movey_url)?),
+            LoginStrategy::Web => issued_credential(web_login(&movey_url)?),
+            LoginStrategy::Device => issued_credential(device_login(&movey_url)?),
+        };
+        // fail before asking the registry if the token cannot be saved
+        let name = registry_name(&MOVE_HOME, registry)?;
+        let store = open_credential_store(&MOVE_HOME, &credential_passphrase)?;
+        let user = MoveyLogin::verify_token(&movey_url, &credential.token)?;
+        // pasted tokens only get their scopes from the registry here
+        if user.scopes.is_some() {
+            credential.scopes = user.scopes;
+        }
+        store.store(&name, &credential)?;
+        println!("Logged in as {}", user.username);
+        println!(
+            "Token for {} saved in {}.",
+            registry_display_name(&name),
+            store.describe()
+        );
+        Ok(())
+    }
+
+    /// Ask the registry who owns `token`, so that only working tokens are
+    /// saved.
+    pub fn verify_token(movey_url: &str, token: &str) -> Result<User> {
+        match movey_client(movey_url).with_token(token).whoami() {
+            Ok(user) => Ok(user),
+            Err(error @ MoveyApiError::AuthFailed { .. }) => {
+                Err(anyhow::Error::new(error)
+                    .context("Movey rejected the API Token, it was not saved"))
+            }
+            Err(error) => Err(anyhow::Error::new(error)
+                .context("Could not verify the API Token, it was not saved")),
+        }
+    }
+
+    fn paste_token(&self, movey_url: &str) -> Result<String> {
+        if self.token_stdin {
+            return MoveyLogin::read_token(io::stdin().lock());
+        }
         println!(
             "Please paste the API Token found on {}/settings/tokens below",
-            MOVEY_URL
+            movey_url
         );
-        let mut line = String::new();
+        if io::stdin().is_terminal() {
+            MoveyLogin::prompt_hidden_token()
+        } else {
+            MoveyLogin::prompt_token(io::stdin().lock())
+        }
+    }
+
+    // like `prompt_token`, without echoing the token to the terminal
+    fn prompt_hidden_token() -> Result<String> {
+        loop {
+            let token = match rpassword::read_password() {
+                Ok(token) => token,
+                Err(err) => bail!("Error reading input: {}", err),
+            };
+            let token = token.trim();
+            if !token.is_empty() {
+                return Ok(token.to_string());
+            }
+            println!("Invalid API Token. Try again!");
+        }
+    }
+
+    /// Read the whole input as the token, failing if it is blank.
+    pub fn read_token(mut input: impl BufRead) -> Result<String> {
+        let mut token = String::new();
+        if let Err(err) = input.read_to_string(&mut token) {
+            bail!("Error reading input: {}", err);
+        }
+        let token = token.trim();
+        if token.is_empty() {
+            bail!("No API Token was provided on stdin")
+        }
+        Ok(token.to_string())
+    }
+
+    /// Read lines until one is not blank, failing when the input ends first.
+    pub fn prompt_token(mut input: impl BufRead) -> Result<String> {
         loop {
-            match io::stdin().read_line(&mut line) {
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) => bail!("No API Token was entered"),
                 Ok(_) => {
-                    line = line.trim().to_string();
-                    if !line.is_empty() {
-                        break;
+                    let token = line.trim();
+                    if !token.is_empty() {
+                        return Ok(token.to_string());
                     }
                     println!("Invalid API Token. Try again!");
                 }
@@ -36,73 +168,21 @@ impl MoveyLogin {
                 }
             }
         }
-        MoveyLogin::save_credential(line, MOVE_HOME.clone())?;
-        println!("Token for Movey saved.");
-        Ok(())
     }
 
+    /// Save the token of the default registry in the credential store.
     pub fn save_credential(token: String, move_home: String) -> Result<()> {
-        fs::create_dir_all(&move_home)?;
-        let credential_path = move_home + MOVEY_CREDENTIAL_PATH;
-        let credential_file = PathBuf::from(&credential_path);
-        if !credential_file.exists() {
-            create_credential_file(&credential_path)?;
-        }
-
-        let mut toml: Value = read_credential_file(&credential_path)?;
-        // only update token key, keep the rest of the file intact
-        if let Some(registry) = toml.as_table_mut().unwrap().get_mut("registry") {
-            if let Some(toml_token) = registry.as_table_mut().unwrap().get_mut("token") {
-                *toml_token = Value::String(token);
-            } else {
-                registry
-                    .as_table_mut()
-                    .unwrap()
-                    .insert(String::from("token"), Value::String(token));
-            }
-        } else {
-            let mut value = Map::new();
-            value.insert(String::from("token"), Value::String(token));
-            toml.as_table_mut()
-                .unwrap()
-                .insert(String::from("registry"), Value::Table(value));
-        }
-
-        let new_contents = toml.to_string();
-        fs::write(credential_file, new_contents).expect("Unable to write file");
-        Ok(())
+        let name = registry_name(&move_home, None)?;
+        open_credential_store(&move_home, &env_passphrase)?.store(&name, &Credential::new(token))
     }
 }
 
-#[cfg(unix)]
-fn create_credential_file(credential_path: &str) -> Result<()> {
-    use std::os::unix::fs::PermissionsExt;
-    let credential_file = File::create(credential_path)?;
-
-    let mut perms = credential_file.metadata()?.permissions();
-    perms.set_mode(0o600);
-    credential_file.set_permissions(perms)?;
-    Ok(())
-}
-
-#[cfg(windows)]
-#[allow(unused)]
-fn create_credential_file(credential_path: &str) -> Result<()> {
-    let windows_path = credential_path.replace("/", "\\");
-    File::create(&windows_path)?;
-    Ok(())
-}
-
-#[cfg(not(any(unix, windows)))]
-#[allow(unused)]
-fn create_credential_file(credential_path: &str) -> Result<()> {
-    bail!("OS not supported")
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
+    use std::{fs, fs::File};
+    use toml_edit::easy::Value;
+    use utils::movey_credential::MOVEY_CREDENTIAL_PATH;
 
     fn setup_move_home(test_path: &str) -> (String, String) {
         let cwd = env::current_dir().unwrap();
@@ -120,6 +200,24 @@ mod tests {
         let _ = fs::remove_dir_all(move_home);
     }
 
+    #[test]
+    fn read_token_works() {
+        let token = MoveyLogin::read_token("  test_token\n".as_bytes()).unwrap();
+        assert_eq!(token, "test_token");
+
+        let error = MoveyLogin::read_token(" \n".as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("No API Token"));
+    }
+
+    #[test]
+    fn prompt_token_skips_blank_lines_and_stops_at_end_of_input() {
+        let token = MoveyLogin::prompt_token("\n \ntest_token\nrest\n".as_bytes()).unwrap();
+        assert_eq!(token, "test_token");
+
+        let error = MoveyLogin::prompt_token("\n\n".as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("No API Token was entered"));
+    }
+
     #[test]
     fn save_credential_works_if_no_credential_file_exists() {
         let (move_home, credential_path) =
@@ -0,0 +1,190 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use movey_client::MoveyApiError;
+use std::{env, path::PathBuf};
+use toml_edit::easy::{map::Map, Value};
+use utils::credential_store::{open_credential_store, CredentialStore};
+use utils::env::MOVE_HOME;
+use utils::movey_credential::{
+    get_env_api_token, read_credential_file, registry_names, registry_token_env, resolve_registry,
+    resolve_token_url, DEFAULT_REGISTRY, MOVEY_CREDENTIAL_PATH,
+};
+
+use super::{credential_passphrase, movey_client, registry_display_name};
+
+/// Remove the API tokens saved by `movey login`, keeping the other settings
+/// of the credential file.
+#[derive(Parser)]
+#[clap(name = "logout", about = "Logout from Movey")]
+pub struct MoveyLogout {
+    /// Revoke the token on the registry before removing it
+    #[clap(long)]
+    pub revoke: bool,
+    /// Name of the registry to logout from [default: registry.default or movey]
+    #[clap(long, value_name = "NAME", conflicts_with = "all")]
+    pub registry: Option<String>,
+    /// Url of the registry to revoke the token on, instead of the configured one
+    #[clap(long, value_name = "URL", conflicts_with = "all")]
+    pub registry_url: Option<String>,
+    /// Logout from every registry
+    #[clap(long)]
+    pub all: bool,
+}
+
+impl MoveyLogout {
+    pub fn execute(&self) -> Result<()> {
+        let credential_path = format!("{}{}", *MOVE_HOME, MOVEY_CREDENTIAL_PATH);
+        let toml = if PathBuf::from(&credential_path).exists() {
+            read_credential_file(&credential_path)?
+        } else {
+            Value::Table(Map::new())
+        };
+        let store = open_credential_store(&MOVE_HOME, &credential_passphrase)?;
+        let registries = if self.all {
+            all_registries(&toml, store.as_ref())?
+        } else {
+            vec![resolve_registry(&toml, self.registry.as_deref())]
+        };
+
+        // with --all, a token which could not be revoked does not stop the
+        // logout from the other registries
+        let mut failures = vec![];
+        for name in &registries {
+            let token = match store.get(name)? {
+                Some(credential) => credential.token,
+                None if self.all => continue,
+                None => {
+                    println!("No token for {} is saved.", registry_display_name(name));
+                    continue;
+                }
+            };
+            if self.revoke {
+                if let Err(error) = self.revoke_token(name, &token) {
+                    failures.push(error);
+                    continue;
+                }
+            }
+            store.erase(name)?;
+            println!("Logged out of {}.", registry_display_name(name));
+        }
+
+        for name in registries
+            .iter()
+            .filter(|name| get_env_api_token(name).is_some())
+        {
+            println!(
+                "The {} environment variable is still set and will be used.",
+                registry_token_env(name)
+            );
+        }
+        match failures.len() {
+            0 => Ok(()),
+            1 => Err(failures.remove(0)),
+            count => {
+                for error in &failures {
+                    eprintln!("error: {:#}", error);
+                }
+                bail!("{} tokens could not be revoked and were kept", count)
+            }
+        }
+    }
+
+    fn revoke_token(&self, name: &str, token: &str) -> Result<()> {
+        let url = resolve_token_url(
+            &MOVE_HOME,
+            Some(name),
+            self.registry_url.as_deref(),
+            Some(&env::current_dir()?),
+        )?
+        .url;
+        revoke_token(&url, token, name)
+    }
+}
+
+/// Names of the registries of the credential file and of those with a token
+/// in `store`, Movey included even if neither has it.
+pub fn all_registries(toml: &Value, store: &dyn CredentialStore) -> Result<Vec<String>> {
+    let mut names = registry_names(toml);
+    for name in store.list()? {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if !names.iter().any(|name| name == DEFAULT_REGISTRY) {
+        names.insert(0, String::from(DEFAULT_REGISTRY));
+    }
+    Ok(names)
+}
+
+fn revoke_token(url: &str, token: &str, name: &str) -> Result<()> {
+    match movey_client(url).with_token(token).revoke_token() {
+        Ok(()) => Ok(()),
+        // the token cannot be used anymore, so it can still be removed; a
+        // 403 is a valid token which may not revoke itself, and is kept
+        Err(MoveyApiError::AuthFailed { status: 401, .. }) => {
+            println!(
+                "The token for {} was already invalid.",
+                registry_display_name(name)
+            );
+            Ok(())
+        }
+        Err(error) => Err(anyhow::Error::new(error).context(format!(
+            "Could not revoke the token for {}, it was kept",
+            registry_display_name(name)
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::credential_store::Credential;
+
+    // store listing `registries`, without any credential
+    struct ListStore(Vec<String>);
+
+    impl CredentialStore for ListStore {
+        fn get(&self, _: &str) -> Result<Option<Credential>> {
+            Ok(None)
+        }
+        fn store(&self, _: &str, _: &Credential) -> Result<()> {
+            Ok(())
+        }
+        fn erase(&self, _: &str) -> Result<bool> {
+            Ok(false)
+        }
+        fn list(&self) -> Result<Vec<String>> {
+            Ok(self.0.clone())
+        }
+        fn describe(&self) -> String {
+            String::from("list store")
+        }
+    }
+
+    #[test]
+    fn all_registries_includes_movey_and_the_store() {
+        let empty = ListStore(vec![]);
+        let toml: Value = "[registries.internal]\ntoken = \"internal-token\"\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            all_registries(&toml, &empty).unwrap(),
+            ["movey", "internal"]
+        );
+
+        let toml: Value = "[registry]\n\n[registries.internal]\n".parse().unwrap();
+        assert_eq!(
+            all_registries(&toml, &empty).unwrap(),
+            ["movey", "internal"]
+        );
+        let store = ListStore(vec![String::from("internal"), String::from("staging")]);
+        assert_eq!(
+            all_registries(&toml, &store).unwrap(),
+            ["movey", "internal", "staging"]
+        );
+        assert_eq!(
+            all_registries(&Value::Table(Map::new()), &empty).unwrap(),
+            ["movey"]
+        );
+    }
+}
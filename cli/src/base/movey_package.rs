@@ -0,0 +1,65 @@
+use anyhow::Result;
+use clap::*;
+use std::path::{Path, PathBuf};
+use utils::file_selection::FileSelector;
+use utils::git::GitRepository;
+use utils::move_manifest::{MoveManifest, PackageInfo, MOVE_MANIFEST_FILE};
+use utils::package_archive::PackageArchive;
+
+/// Build the source archive that `movey upload` sends, for inspection.
+#[derive(Parser)]
+#[clap(name = "package", about = "Build the source archive of the package")]
+pub struct MoveyPackage {
+    /// Directory the archive is written to
+    #[clap(long, value_name = "DIR", default_value = "build")]
+    pub output_dir: PathBuf,
+    /// Print the files that would be published instead of building the archive
+    #[clap(long)]
+    pub list: bool,
+}
+
+impl MoveyPackage {
+    pub fn execute(&self) -> Result<()> {
+        let manifest = MoveManifest::read(Path::new(MOVE_MANIFEST_FILE))?;
+        let repository = GitRepository::discover(Path::new("."))?;
+        let files = MoveyPackage::package_files(Path::new("."), &manifest.package, &repository)?;
+        if self.list {
+            for file in files {
+                println!("{}", file.display());
+            }
+            return Ok(());
+        }
+
+        let archive = MoveyPackage::build_archive(Path::new("."), &manifest.package, &files)?;
+        let archive_path = archive.write(&self.output_dir)?;
+        println!(
+            "Packaged {} files of {} to {}",
+            archive.total_files,
+            manifest.package.name,
+            archive_path.display()
+        );
+        println!("SHA-256: {}", archive.sha256);
+        Ok(())
+    }
+
+    /// Files of the package in `package_root` that get published: the
+    /// git-tracked files kept by `.moveyignore` and `include`/`exclude`.
+    /// `repository` must be discovered from `package_root`.
+    pub fn package_files(
+        package_root: &Path,
+        package: &PackageInfo,
+        repository: &GitRepository,
+    ) -> Result<Vec<PathBuf>> {
+        let selector = FileSelector::new(package_root, package)?;
+        Ok(selector.select(&repository.tracked_files()?))
+    }
+
+    /// Archive `files`, given relative to `package_root`.
+    pub fn build_archive(
+        package_root: &Path,
+        package: &PackageInfo,
+        files: &[PathBuf],
+    ) -> Result<PackageArchive> {
+        PackageArchive::build(package_root, files, &package.name, &package.version)
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use movey_client::{scope, ApiToken, MoveyClient, NewApiToken};
+
+use super::{authenticated_client, registry_credential, registry_display_name, require_scope};
+
+/// Manage the API tokens of the account, like the `/settings/tokens` page of
+/// the registry.
+#[derive(Parser)]
+#[clap(name = "token", about = "Manage the API tokens of the account")]
+pub struct MoveyToken {
+    #[clap(subcommand)]
+    pub command: TokenCommand,
+    /// Name of the registry [default: registry.default or movey]
+    #[clap(long, value_name = "NAME", global = true)]
+    pub registry: Option<String>,
+    /// Url of the registry, instead of the configured one
+    #[clap(long, value_name = "URL", global = true)]
+    pub registry_url: Option<String>,
+    /// Print the result as JSON
+    #[clap(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommand {
+    /// List the API tokens of the account
+    List,
+    /// Create an API token, which is only shown once
+    Create {
+        /// Name of the token, to recognize it in the list
+        #[clap(long)]
+        name: String,
+        /// Comma separated scopes of the token: publish, read or tokens
+        #[clap(
+            long,
+            value_name = "SCOPES",
+            use_value_delimiter = true,
+            required = true,
+            parse(try_from_str = parse_scope)
+        )]
+        scopes: Vec<String>,
+        /// Time until the token expires, like `90d`, `12h`, `30m` or `60s`
+        /// [default: never]
+        #[clap(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+        expires: Option<u64>,
+    },
+    /// Revoke an API token, which cannot be used after
+    Revoke {
+        /// Id of the token, as listed by `movey token list`
+        id: u64,
+    },
+}
+
+impl TokenCommand {
+    /// Scope the token needs to run the subcommand.
+    pub fn scope(&self) -> &'static str {
+        match self {
+            TokenCommand::List => scope::READ,
+            TokenCommand::Create { .. } | TokenCommand::Revoke { .. } => scope::TOKENS,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TokenCommand::List => "token list",
+            TokenCommand::Create { .. } => "token create",
+            TokenCommand::Revoke { .. } => "token revoke",
+        }
+    }
+}
+
+impl MoveyToken {
+    pub fn execute(&self) -> Result<()> {
+        let (credential, url) =
+            registry_credential(self.registry.as_deref(), self.registry_url.as_deref())?;
+        require_scope(&credential, self.command.scope(), self.command.name())?;
+        let registry = registry_display_name(&credential.name);
+        let client = authenticated_client(&url, credential);
+        match &self.command {
+            TokenCommand::List => self.list(&client, &registry),
+            TokenCommand::Create {
+                name,
+                scopes,
+                expires,
+            } => self.create(&client, &registry, name, scopes, *expires),
+            TokenCommand::Revoke { id } => self.revoke(&client, &registry, *id),
+        }
+    }
+
+    fn list(&self, client: &MoveyClient, registry: &str) -> Result<()> {
+        let tokens = client
+            .list_tokens()
+            .with_context(|| format!("Could not list the tokens of {}", registry))?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&tokens)?);
+        } else if tokens.is_empty() {
+            println!("No token on {}.", registry);
+        } else {
+            print!("{}", token_table(&tokens));
+        }
+        Ok(())
+    }
+
+    fn create(
+        &self,
+        client: &MoveyClient,
+        registry: &str,
+        name: &str,
+        scopes: &[String],
+        expires_in: Option<u64>,
+    ) -> Result<()> {
+        let created = client
+            .create_token(&NewApiToken {
+                name,
+                scopes,
+                expires_in,
+            })
+            .with_context(|| format!("Could not create a token on {}", registry))?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&created)?);
+            return Ok(());
+        }
+        println!(
+            "Created token `{}` (id {}) on {}, it will not be shown again:",
+            created.api_token.name, created.api_token.id, registry
+        );
+        println!("{}", created.token);
+        Ok(())
+    }
+
+    fn revoke(&self, client: &MoveyClient, registry: &str, id: u64) -> Result<()> {
+        client
+            .revoke_token_by_id(id)
+            .with_context(|| format!("Could not revoke the token {} on {}", id, registry))?;
+        if self.json {
+            println!("{}", serde_json::json!({ "revoked": id }));
+        } else {
+            println!("Revoked the token {} on {}.", id, registry);
+        }
+        Ok(())
+    }
+}
+
+/// Seconds of a duration like `90d`, `12h`, `30m` or `60s`.
+pub fn parse_duration(duration: &str) -> Result<u64> {
+    let duration = duration.trim();
+    let unit_start = duration.char_indices().last().map_or(0, |(index, _)| index);
+    let (count, unit) = duration.split_at(unit_start);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("the duration must end with s, m, h or d, like 90d"),
+    };
+    let count: u64 = count
+        .parse()
+        .with_context(|| format!("invalid duration `{}`", duration))?;
+    if count == 0 {
+        bail!("the duration must not be zero")
+    }
+    count
+        .checked_mul(seconds)
+        .with_context(|| format!("the duration `{}` is too long", duration))
+}
+
+/// `scope` if it is one of [`scope::ALL`], so that a typo is not sent to
+/// the registry.
+pub fn parse_scope(scope: &str) -> Result<String> {
+    if !scope::ALL.contains(&scope) {
+        bail!(
+            "unknown scope `{}`, expected one of {}",
+            scope,
+            scope::ALL.join(", ")
+        )
+    }
+    Ok(scope.to_string())
+}
+
+// the tokens with one column per field, each as wide as its longest value
+fn token_table(tokens: &[ApiToken]) -> String {
+    let optional = |value: &Option<String>, default: &str| {
+        value.clone().unwrap_or_else(|| default.to_string())
+    };
+    let mut rows =
+        vec![["ID", "NAME", "SCOPES", "CREATED", "EXPIRES", "LAST USED"].map(String::from)];
+    rows.extend(tokens.iter().map(|token| {
+        [
+            token.id.to_string(),
+            token.name.clone(),
+            token.scopes.join(","),
+            optional(&token.created_at, "-"),
+            optional(&token.expires_at, "never"),
+            optional(&token.last_used_at, "never"),
+        ]
+    }));
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_reads_the_unit() {
+        assert_eq!(parse_duration("90d").unwrap(), 90 * 24 * 60 * 60);
+        assert_eq!(parse_duration("12h").unwrap(), 12 * 60 * 60);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("60s").unwrap(), 60);
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("0d").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("9é").is_err());
+    }
+
+    #[test]
+    fn parse_scope_only_accepts_known_scopes() {
+        assert_eq!(parse_scope("publish").unwrap(), "publish");
+        assert_eq!(parse_scope("tokens").unwrap(), "tokens");
+        let error = parse_scope("admin").unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "unknown scope `admin`, expected one of publish, read, tokens"
+        );
+        assert!(parse_scope("Publish").is_err());
+    }
+
+    #[test]
+    fn token_table_aligns_the_columns() {
+        let tokens = [
+            ApiToken {
+                id: 1,
+                name: String::from("laptop"),
+                scopes: vec![String::from("publish"), String::from("read")],
+                created_at: Some(String::from("2024-01-01")),
+                expires_at: None,
+                last_used_at: None,
+            },
+            ApiToken {
+                id: 12,
+                name: String::from("ci"),
+                scopes: vec![String::from("publish")],
+                created_at: None,
+                expires_at: Some(String::from("2025-01-01")),
+                last_used_at: Some(String::from("2024-06-01")),
+            },
+        ];
+        assert_eq!(
+            token_table(&tokens),
+            "ID  NAME    SCOPES        CREATED     EXPIRES     LAST USED\n\
+             1   laptop  publish,read  2024-01-01  never       never\n\
+             12  ci      publish       -           2025-01-01  2024-06-01\n"
+        );
+    }
+}
@@ -1,131 +1,327 @@
 use anyhow::{bail, Result};
 use clap::*;
-use reqwest::blocking::Client;
-use std::{env, fs::File, path::PathBuf, process::Command};
+use movey_client::retry::idempotency_key;
+use movey_client::scope;
+use movey_client::{MoveyApiError, MoveyClient, RetryPolicy, UploadRequest};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use utils::env::MOVE_HOME;
-use utils::movey_credential;
+use utils::git::GitRepository;
+use utils::git_remote::normalize_remote_url;
+use utils::move_manifest::{Dependency, MoveManifest, PackageInfo, MOVE_MANIFEST_FILE};
+use utils::move_workspace::{local_dependencies, workspace_packages};
+use utils::movey_credential::{self, RegistryUrl};
+
+use super::movey_package::MoveyPackage;
+use super::{authenticated_client, credential_passphrase, credential_url, require_scope};
+
+// Remote used when `--remote` is not given, if the repository has it
+const DEFAULT_REMOTE: &str = "origin";
 
 // Metadata that will be collected by Movey
 #[derive(serde::Serialize, Default)]
 pub struct MoveyUploadRequest {
-    github_repo_url: String,
+    repository_url: String,
     total_files: usize,
-    token: String,
     subdir: String,
+    commit_sha: Option<String>,
+    tag: Option<String>,
+    branch: Option<String>,
+    dirty: bool,
+    archive_sha256: String,
+    package: PackageInfo,
+    addresses: BTreeMap<String, String>,
+    dependencies: BTreeMap<String, Dependency>,
+    dev_dependencies: BTreeMap<String, Dependency>,
 }
 
-/// Upload the package metadata to Movey.net.
-#[derive(Parser)]
-#[clap(name = "upload")]
-pub struct MoveyUpload;
+/// Upload the package metadata and source archive to Movey.net.
+#[derive(Parser, Default)]
+#[clap(name = "upload", about = "Upload to Movey")]
+pub struct MoveyUpload {
+    /// Print the request that would be sent to Movey without uploading it
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Name of the git remote to read the repository url from [default: origin]
+    #[clap(long, value_name = "NAME")]
+    pub remote: Option<String>,
+    /// Allow uploading a package with uncommitted changes
+    #[clap(long)]
+    pub allow_dirty: bool,
+    /// Upload every package of the git repository, dependencies first
+    #[clap(long)]
+    pub workspace: bool,
+    /// Number of times a failed upload is retried
+    #[clap(long, value_name = "N", default_value = "3")]
+    pub retries: u32,
+    /// Name of the registry to upload to [default: registry.default or movey]
+    #[clap(long, value_name = "NAME")]
+    pub registry: Option<String>,
+    /// Url of the registry, instead of the configured one
+    #[clap(long, value_name = "URL")]
+    pub registry_url: Option<String>,
+    /// Directory of the package to upload [default: current directory]
+    #[clap(long, value_name = "DIR", conflicts_with = "manifest-path")]
+    pub path: Option<PathBuf>,
+    /// Path to the Move.toml of the package to upload
+    #[clap(long, value_name = "PATH")]
+    pub manifest_path: Option<PathBuf>,
+}
 
 impl MoveyUpload {
-    pub fn execute(path: Option<PathBuf>) -> Result<()> {
-        if let Some(path) = path {
-            if path.exists() && path.is_dir() {
-                let _ = env::set_current_dir(&path);
-            } else {
-                bail!("invalid directory")
-            }
+    /// Scope the token needs to upload.
+    pub const SCOPE: &'static str = scope::PUBLISH;
+
+    pub fn execute(&self) -> Result<()> {
+        if self.workspace {
+            return self.execute_workspace();
         }
-        // make sure it's a Move project
-        let move_toml = File::open("Move.toml");
-        if move_toml.is_err() {
-            bail!("Move.toml not found")
+        let (package_root, manifest_path) = self.package_location()?;
+        let client = self.client(&package_root)?;
+        self.upload_package(&package_root, &manifest_path, None, client)
+    }
+
+    // upload the package of `manifest_path` in `package_root`, which is in
+    // `repository` or discovered from it; `client` is `None` for a dry run
+    fn upload_package(
+        &self,
+        package_root: &Path,
+        manifest_path: &Path,
+        repository: Option<&GitRepository>,
+        client: Option<Result<MoveyClient>>,
+    ) -> Result<()> {
+        // make sure it's a Move project and collect the package metadata
+        let manifest = MoveManifest::read(manifest_path)?;
+        let mut movey_upload_request = MoveyUploadRequest {
+            package: manifest.package,
+            addresses: manifest.addresses,
+            dependencies: manifest.dependencies,
+            dev_dependencies: manifest.dev_dependencies,
+            ..Default::default()
+        };
+
+        // read the repository url from the git remotes
+        let discovered;
+        let repository = match repository {
+            Some(repository) => repository,
+            None => {
+                discovered = GitRepository::discover(package_root)?;
+                &discovered
+            }
+        };
+        let remotes = repository.remotes()?;
+        let remote = match &self.remote {
+            Some(name) => match remotes.iter().find(|remote| &remote.name == name) {
+                Some(remote) => remote,
+                None => bail!("git remote `{}` not found", name),
+            },
+            None => match remotes.iter().find(|remote| remote.name == DEFAULT_REMOTE) {
+                Some(remote) => remote,
+                None => match remotes.first() {
+                    Some(remote) => remote,
+                    None => bail!("invalid git repository"),
+                },
+            },
+        };
+        movey_upload_request.repository_url = normalize_remote_url(&remote.url)?;
+
+        // subdir is the package path from the repository root, like `git rev-parse --show-prefix`
+        movey_upload_request.subdir = repository
+            .prefix()
+            .iter()
+            .map(|component| format!("{}/", component.to_string_lossy()))
+            .collect();
+        let files =
+            MoveyPackage::package_files(package_root, &movey_upload_request.package, repository)?;
+
+        // record the revision the metadata comes from
+        let dirty_files = repository.dirty_files()?;
+        if !dirty_files.is_empty() && !self.allow_dirty {
+            let files: Vec<String> = dirty_files
+                .iter()
+                .map(|file| format!("  {}", file.display()))
+                .collect();
+            bail!(
+                "{} files in the package directory contain changes that were not yet \
+                committed into git:\n\n{}\n\n\
+                to proceed despite this, pass the `--allow-dirty` flag",
+                dirty_files.len(),
+                files.join("\n")
+            )
         }
-        let metadata = move_toml.unwrap().metadata()?;
-        if metadata.len() == 0 {
-            bail!("Move.toml not found")
+        movey_upload_request.dirty = !dirty_files.is_empty();
+        movey_upload_request.commit_sha = repository.head_commit()?;
+        movey_upload_request.tag = repository.head_tag()?;
+        movey_upload_request.branch = repository.head_branch()?;
+
+        let archive =
+            MoveyPackage::build_archive(package_root, &movey_upload_request.package, &files)?;
+        movey_upload_request.total_files = archive.total_files;
+        movey_upload_request.archive_sha256 = archive.sha256.clone();
+        let client = match client {
+            Some(client) => client?,
+            // the token is only sent in the `Authorization` header, so a
+            // dry run does not need one
+            None => {
+                println!("{}", serde_json::to_string_pretty(&movey_upload_request)?);
+                return Ok(());
+            }
+        };
+        // the same package content always gets the same key, so the
+        // registry can recognize retried uploads
+        let key = idempotency_key(&[
+            &movey_upload_request.repository_url,
+            &movey_upload_request.subdir,
+            &movey_upload_request.package.name,
+            &movey_upload_request.package.version,
+            &movey_upload_request.archive_sha256,
+        ]);
+        let response = client.upload(&UploadRequest {
+            metadata: &movey_upload_request,
+            archive_file_name: archive.file_name,
+            archive: archive.bytes,
+            idempotency_key: Some(key),
+        });
+        match response {
+            Ok(response) => println!(
+                "Your package has been successfully uploaded to Movey at {}.",
+                response.url
+            ),
+            Err(
+                error @ (MoveyApiError::Server { .. }
+                | MoveyApiError::UnexpectedStatus { .. }
+                | MoveyApiError::Transport(_)),
+            ) => {
+                return Err(anyhow::Error::new(error)
+                    .context("An unexpected error occurred. Please try again later"))
+            }
+            // the registry explains what is wrong with the upload
+            Err(error) => return Err(error.into()),
         }
+        Ok(())
+    }
 
-        // use git command to get the repository url
-        let mut movey_upload_request: MoveyUploadRequest = Default::default();
-        let mut output = Command::new("git")
-            .current_dir(".")
-            .args(["remote", "-v"])
-            .output()
-            .unwrap();
-        if !output.status.success() || output.stdout.is_empty() {
-            bail!("invalid git repository")
+    /// The client to upload with, `None` for a dry run. A token known to
+    /// lack [`MoveyUpload::SCOPE`] is refused before the git and packaging
+    /// work, while a missing token, or a registry without url, is only
+    /// reported after it.
+    fn client(&self, project_dir: &Path) -> Result<Option<Result<MoveyClient>>> {
+        if self.dry_run {
+            return Ok(None);
         }
+        let registry = self.registry.as_deref();
+        let credential = match movey_credential::get_registry_credential(
+            &MOVE_HOME,
+            registry,
+            &credential_passphrase,
+        ) {
+            Ok(credential) => credential,
+            Err(error) => return Ok(Some(Err(error))),
+        };
+        require_scope(&credential, MoveyUpload::SCOPE, "upload")?;
+        let client = credential_url(&credential, self.registry_url.as_deref(), project_dir).map(
+            |RegistryUrl { url, .. }| {
+                authenticated_client(&url, credential)
+                    .with_retry_policy(RetryPolicy::with_max_retries(self.retries))
+            },
+        );
+        Ok(Some(client))
+    }
 
-        let lines = String::from_utf8_lossy(output.stdout.as_slice());
-        let lines = lines.split('\n');
-        for line in lines {
-            if line.contains("github.com") {
-                let tokens: Vec<&str> = line.split(&['\t', ' '][..]).collect();
-                if tokens.len() != 3 {
-                    bail!("invalid remote url")
+    /// Directory of the package and path of its manifest, from `--path` or
+    /// `--manifest-path`. The working directory is used by default.
+    fn package_location(&self) -> Result<(PathBuf, PathBuf)> {
+        let manifest_path = match (&self.manifest_path, &self.path) {
+            (Some(manifest_path), _) => manifest_path.clone(),
+            (None, Some(path)) => {
+                if !path.is_dir() {
+                    bail!("invalid directory {}", path.display())
                 }
-                // convert ssh url to https
-                let https_url = if tokens[1].starts_with("git@github.com") {
-                    let author_slash_repo = tokens[1].split(':').last().unwrap();
-                    format!("https://github.com/{author_slash_repo}")
-                } else {
-                    String::from(tokens[1])
-                };
-                movey_upload_request.github_repo_url = if https_url.ends_with(".git") {
-                    https_url[..https_url.len() - 4].to_string()
-                } else {
-                    https_url
-                };
+                path.join(MOVE_MANIFEST_FILE)
             }
+            (None, None) => PathBuf::from(MOVE_MANIFEST_FILE),
+        };
+        let package_root = match manifest_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        Ok((package_root, manifest_path))
+    }
+
+    /// Upload every package tracked in the git repository of the package
+    /// directory, each one after the workspace packages it depends on. A
+    /// package whose local dependency failed to upload is skipped.
+    fn execute_workspace(&self) -> Result<()> {
+        let (package_root, _) = self.package_location()?;
+        let mut repository = GitRepository::discover(&package_root)?;
+        let root = repository.workdir().to_path_buf();
+        repository.set_prefix("");
+        let manifests: Vec<PathBuf> = repository
+            .tracked_files()?
+            .into_iter()
+            .filter(|file| file.file_name() == Some(MOVE_MANIFEST_FILE.as_ref()))
+            .collect();
+        let packages = workspace_packages(&root, &manifests)?;
+        if packages.is_empty() {
+            bail!("no {} found in {}", MOVE_MANIFEST_FILE, root.display())
         }
 
-        // use git command to get the subdir if move package is not on the top level
-        output = Command::new("git")
-            .current_dir(".")
-            .args(["rev-parse", "--show-prefix"])
-            .output()
-            .unwrap();
-        let subdir = String::from_utf8_lossy(output.stdout.as_slice());
-        movey_upload_request.subdir = String::from(subdir);
-
-        // use git command to count total files
-        output = Command::new("git")
-            .current_dir(".")
-            .args(["ls-files"])
-            .output()
-            .unwrap();
-        let tracked_files = String::from_utf8_lossy(output.stdout.as_slice());
-        let tracked_files: Vec<&str> = tracked_files.split('\n').collect();
-        let mut total_files = tracked_files.len();
-        for file_path in tracked_files {
-            if file_path.is_empty() {
-                total_files -= 1;
-                continue;
-            }
+        // one client for every package, so that the token is looked up,
+        // and a refreshed token is used, by all of them
+        let client = self.client(&root)?.transpose()?;
+
+        let mut results: Vec<std::result::Result<(), String>> = vec![];
+        for package in &packages {
+            let failed_dependency = local_dependencies(package, &packages)
+                .into_iter()
+                .find(|dependency| results[*dependency].is_err());
+            let result = match failed_dependency {
+                Some(dependency) => Err(format!(
+                    "skipped, dependency {} was not uploaded",
+                    packages[dependency].manifest.package.name
+                )),
+                None => {
+                    let package_root = root.join(&package.path);
+                    repository.set_prefix(&package.path);
+                    self.upload_package(
+                        &package_root,
+                        &package_root.join(MOVE_MANIFEST_FILE),
+                        Some(&repository),
+                        client.clone().map(Ok),
+                    )
+                    .map_err(|error| format!("{:#}", error))
+                }
+            };
+            results.push(result);
         }
-        movey_upload_request.total_files = total_files;
-        movey_upload_request.token = movey_credential::get_registry_api_token(&MOVE_HOME)?;
-        let movey_url = movey_credential::get_movey_url(&MOVE_HOME);
-        match movey_url {
-            Ok(url) => {
-                let client = Client::new();
-                let response = client
-                    .post(&format!("{}/api/v1/packages/upload", &url))
-                    .json(&movey_upload_request)
-                    .send();
-                match response {
-                    Ok(response) => {
-                        if response.status().is_success() {
-                            println!(
-                                "Your package has been successfully uploaded to Movey at {}/packages/{}.",
-                                url,
-                                response.text()?
-                            );
-                        } else if response.status().is_client_error() {
-                            bail!("{}", response.text()?)
-                        } else if response.status().is_server_error() {
-                            bail!("An unexpected error occurred. Please try again later");
-                        }
-                    }
-                    Err(_) => {
-                        bail!("An unexpected error occurred. Please try again later");
-                    }
+
+        println!("\nWorkspace upload summary:");
+        let mut failures = 0;
+        for (package, result) in packages.iter().zip(&results) {
+            let path = if package.path.as_os_str().is_empty() {
+                String::from(".")
+            } else {
+                package.path.display().to_string()
+            };
+            match result {
+                Ok(()) => println!("  ok      {} ({})", package.manifest.package.name, path),
+                Err(error) => {
+                    failures += 1;
+                    println!(
+                        "  failed  {} ({}): {}",
+                        package.manifest.package.name, path, error
+                    );
                 }
             }
-            Err(_) => bail!("An unexpected error occurred. Please try again later"),
+        }
+        if failures > 0 {
+            bail!(
+                "{} of {} packages failed to upload",
+                failures,
+                packages.len()
+            )
         }
         Ok(())
     }
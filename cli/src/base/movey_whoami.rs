@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::Serialize;
+
+use super::{authenticated_client, registry_credential, registry_display_name};
+
+/// Show the account owning the API token, and what the token may do.
+#[derive(Parser)]
+#[clap(name = "whoami", about = "Show the account of the API token")]
+pub struct MoveyWhoami {
+    /// Name of the registry [default: registry.default or movey]
+    #[clap(long, value_name = "NAME")]
+    pub registry: Option<String>,
+    /// Url of the registry, instead of the configured one
+    #[clap(long, value_name = "URL")]
+    pub registry_url: Option<String>,
+    /// Print the account as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Serialize)]
+struct Whoami {
+    registry: String,
+    url: String,
+    username: String,
+    /// `None` when neither the registry nor the login said.
+    scopes: Option<Vec<String>>,
+}
+
+impl MoveyWhoami {
+    pub fn execute(&self) -> Result<()> {
+        let (credential, url) =
+            registry_credential(self.registry.as_deref(), self.registry_url.as_deref())?;
+        let registry = credential.name.clone();
+        let saved_scopes = credential.credential.scopes.clone();
+        let user = authenticated_client(&url, credential)
+            .whoami()
+            .with_context(|| {
+                format!(
+                    "Could not get the account of {}",
+                    registry_display_name(&registry)
+                )
+            })?;
+        let whoami = Whoami {
+            scopes: user.scopes.or(saved_scopes),
+            username: user.username,
+            registry,
+            url,
+        };
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&whoami)?);
+            return Ok(());
+        }
+        println!(
+            "Logged in to {} as {}.",
+            registry_display_name(&whoami.registry),
+            whoami.username
+        );
+        match &whoami.scopes {
+            Some(scopes) if scopes.is_empty() => println!("scopes: none"),
+            Some(scopes) => println!("scopes: {}", scopes.join(", ")),
+            None => println!("scopes: unknown"),
+        }
+        Ok(())
+    }
+}
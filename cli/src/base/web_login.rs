@@ -0,0 +1,196 @@
+use anyhow::{anyhow, bail, Context, Result};
+use movey_client::oauth::{
+    random_token, AuthorizationRequest, AuthorizationResponse, Pkce, TokenRequest, TokenResponse,
+};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::movey_client;
+
+/// How long the browser has to come back with the code.
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+const CALLBACK_PATH: &str = "/callback";
+
+/// Get a token by authorizing the CLI in the browser: the registry redirects
+/// the browser to a listener on 127.0.0.1 with a code, which is exchanged
+/// for the token.
+pub fn web_login(movey_url: &str) -> Result<TokenResponse> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .context("Could not start the server waiting for the browser")?;
+    let redirect_uri = format!(
+        "http://127.0.0.1:{}{}",
+        listener.local_addr()?.port(),
+        CALLBACK_PATH
+    );
+    let pkce = Pkce::generate()?;
+    let state = random_token()?;
+    let client = movey_client(movey_url);
+    let authorize_url = client.authorize_url(&AuthorizationRequest {
+        redirect_uri: &redirect_uri,
+        state: &state,
+        code_challenge: &pkce.challenge,
+    });
+
+    println!("Open this url in your browser to login to Movey:");
+    println!("{}", authorize_url);
+    println!("Waiting for the browser...");
+    let code = wait_for_code(&listener, &state, CALLBACK_TIMEOUT)?;
+    client
+        .exchange_code(&TokenRequest::authorization_code(
+            &code,
+            &pkce.verifier,
+            &redirect_uri,
+        ))
+        .context("Could not get a token for the authorization")
+}
+
+// answer the requests to `listener` until one brings the code of the
+// authorization identified by `state`
+fn wait_for_code(listener: &TcpListener, state: &str, timeout: Duration) -> Result<String> {
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    bail!("The browser did not come back within {:?}", timeout)
+                }
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if let Some(code) = handle_callback(stream, state)? {
+            return Ok(code);
+        }
+    }
+}
+
+// `None` when the request is not the callback, so that the next one is
+// waited for
+fn handle_callback(mut stream: TcpStream, state: &str) -> Result<Option<String>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are not used, but are read so that the browser is not
+    // reset before it gets the answer
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != CALLBACK_PATH {
+        respond(&mut stream, "404 Not Found", "Not found.")?;
+        return Ok(None);
+    }
+
+    let response = AuthorizationResponse::from_query(query);
+    let result = if response.state.as_deref() != Some(state) {
+        Err(anyhow!(
+            "The browser came back from another authorization, login again"
+        ))
+    } else if let Some(error) = response.error {
+        Err(anyhow!(
+            "The authorization was denied: {}",
+            response.error_description.unwrap_or(error)
+        ))
+    } else {
+        response
+            .code
+            .ok_or_else(|| anyhow!("The browser came back without an authorization code"))
+    };
+    match result {
+        Ok(code) => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "Movey CLI is authorized, you can close this page.",
+            )?;
+            Ok(Some(code))
+        }
+        Err(error) => {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "Login failed, see the terminal.",
+            )?;
+            Err(error)
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, message: &str) -> Result<()> {
+    let body = format!(
+        "<!DOCTYPE html><html><body><p>{}</p></body></html>",
+        message
+    );
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn request(port: u16, target: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn wait_for_code_skips_other_paths_and_checks_the_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let browser = thread::spawn(move || {
+            (
+                request(port, "/favicon.ico"),
+                request(port, "/callback?code=test-code&state=test-state"),
+            )
+        });
+        let code = wait_for_code(&listener, "test-state", Duration::from_secs(10)).unwrap();
+        let (not_found, ok) = browser.join().unwrap();
+        assert_eq!(code, "test-code");
+        assert!(not_found.starts_with("HTTP/1.1 404"));
+        assert!(ok.starts_with("HTTP/1.1 200"));
+
+        let browser =
+            thread::spawn(move || request(port, "/callback?code=test-code&state=other-state"));
+        let error = wait_for_code(&listener, "test-state", Duration::from_secs(10)).unwrap_err();
+        assert!(browser.join().unwrap().starts_with("HTTP/1.1 400"));
+        assert!(error.to_string().contains("another authorization"));
+
+        let browser = thread::spawn(move || {
+            request(
+                port,
+                "/callback?error=access_denied&error_description=Denied+by+user&state=test-state",
+            )
+        });
+        let error = wait_for_code(&listener, "test-state", Duration::from_secs(10)).unwrap_err();
+        browser.join().unwrap();
+        assert!(error.to_string().contains("Denied by user"));
+
+        let error = wait_for_code(&listener, "test-state", Duration::ZERO).unwrap_err();
+        assert!(error.to_string().contains("did not come back"));
+    }
+}
@@ -0,0 +1,81 @@
+//! Exit codes of `movey`, so that scripts can tell failures apart.
+//!
+//! | Code | Meaning                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | Success                                                  |
+//! | 1    | Any other failure                                        |
+//! | 2    | Invalid command line arguments                           |
+//! | 3    | The registry rejected the API token                      |
+//! | 4    | This version of the package was already published        |
+//! | 5    | The registry rejected some values of the package         |
+//! | 6    | Too many requests were sent to the registry              |
+//! | 7    | The registry failed or responded unexpectedly            |
+//! | 8    | The registry could not be reached                        |
+
+use movey_client::MoveyApiError;
+use utils::error::Error;
+
+pub const SUCCESS: i32 = 0;
+pub const FAILURE: i32 = 1;
+pub const USAGE: i32 = 2;
+pub const AUTH_FAILED: i32 = 3;
+pub const VERSION_EXISTS: i32 = 4;
+pub const VALIDATION_FAILED: i32 = 5;
+pub const RATE_LIMITED: i32 = 6;
+pub const SERVER_ERROR: i32 = 7;
+pub const NETWORK_ERROR: i32 = 8;
+
+/// Exit code for `error`, from the first registry error in its chain.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    let api_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<MoveyApiError>());
+    match api_error {
+        Some(MoveyApiError::AuthFailed { .. }) => AUTH_FAILED,
+        Some(MoveyApiError::VersionExists { .. }) => VERSION_EXISTS,
+        Some(MoveyApiError::Validation { .. }) => VALIDATION_FAILED,
+        Some(MoveyApiError::RateLimited { .. }) => RATE_LIMITED,
+        Some(MoveyApiError::Server { .. }) | Some(MoveyApiError::UnexpectedStatus { .. }) => {
+            SERVER_ERROR
+        }
+        Some(MoveyApiError::Transport(error)) if error.status().is_none() => NETWORK_ERROR,
+        Some(MoveyApiError::Transport(_)) => SERVER_ERROR,
+        Some(MoveyApiError::Rejected { .. }) | Some(MoveyApiError::InvalidRequest(_)) | None => {
+            FAILURE
+        }
+    }
+}
+
+/// Exit code for an error returned by [`cli_match`](crate::cli_match).
+pub fn error_exit_code(error: &Error) -> i32 {
+    match error {
+        Error::Command(error) => exit_code(error),
+        _ => FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_works() {
+        let error = anyhow::Error::new(MoveyApiError::from_response(401, "", None));
+        assert_eq!(exit_code(&error), AUTH_FAILED);
+
+        let error = anyhow::Error::new(MoveyApiError::from_response(409, "", None))
+            .context("could not upload Package1");
+        assert_eq!(exit_code(&error), VERSION_EXISTS);
+
+        let error = anyhow::Error::new(MoveyApiError::from_response(502, "", None));
+        assert_eq!(exit_code(&error), SERVER_ERROR);
+
+        assert_eq!(exit_code(&anyhow::anyhow!("Move.toml not found")), FAILURE);
+
+        let error = Error::from(anyhow::Error::new(MoveyApiError::from_response(
+            429, "", None,
+        )));
+        assert_eq!(error_exit_code(&error), RATE_LIMITED);
+        assert_eq!(error_exit_code(&Error::Poison), FAILURE);
+    }
+}
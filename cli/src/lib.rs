@@ -1,37 +1,63 @@
-use clap::{App, AppSettings};
+use clap::{App, AppSettings, Arg, CommandFactory, FromArgMatches};
 use clap::{crate_version, crate_description, crate_authors};
 
 use core::commands;
+use std::env;
+use utils::app_config::AppConfig;
 use utils::error::Result;
 
 pub mod base;
+pub mod exit_code;
+use base::movey_config::MoveyConfig;
 use base::movey_login::MoveyLogin;
+use base::movey_logout::MoveyLogout;
+use base::movey_package::MoveyPackage;
+use base::movey_token::MoveyToken;
 use base::movey_upload::MoveyUpload;
+use base::movey_whoami::MoveyWhoami;
 
 /// Match commands
 pub fn cli_match() -> Result<()> {
     // Get matches
     let cli_matches = cli_config()?;
 
+    // Capture the backtraces of errors, so that they can be reported
+    if cli_matches.is_present("verbose") {
+        env::set_var("RUST_LIB_BACKTRACE", "1");
+        AppConfig::set("debug", "true")?;
+    }
+
     // Merge clap config file if the value is set
     // AppConfig::merge_config(cli_matches.value_of("config"))?;
 
     // Matches Commands or display help
-    match cli_matches.subcommand_name() {
-        Some("hazard") => {
+    match cli_matches.subcommand() {
+        Some(("hazard", _)) => {
             commands::hazard()?;
         }
-        Some("error") => {
+        Some(("error", _)) => {
             commands::simulate_error()?;
         }
-        Some("config") => {
-            commands::config()?;
+        Some(("config", matches)) => {
+            MoveyConfig::from_arg_matches(matches)?.execute()?
+        }
+        Some(("login", matches)) => {
+            MoveyLogin::from_arg_matches(matches)?.execute()?
+        }
+        Some(("logout", matches)) => {
+            MoveyLogout::from_arg_matches(matches)?.execute()?
+        }
+        Some(("upload", matches)) => {
+            MoveyUpload::from_arg_matches(matches)?.execute()?
+        }
+        Some(("package", matches)) => {
+            MoveyPackage::from_arg_matches(matches)?.execute()?
+        }
+        Some(("whoami", matches)) => {
+            MoveyWhoami::from_arg_matches(matches)?.execute()?
         }
-        Some("login") => {
-            MoveyLogin::execute()?;
-        },
-        Some("upload") => {
-            MoveyUpload::execute(None)?
+        Some(("token", matches)) => {
+            MoveyToken::from_arg_matches(matches)?.execute()?
         }
         _ => {
             // Arguments are required by default (in Clap)
@@ -50,8 +76,20 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
         .version(crate_version!())
         .about(crate_description!())
         .author(crate_authors!("\n"))
-        .subcommand(App::new("login").about("Login to Movey"))
-        .subcommand(App::new("upload").about("Upload to Movey"));
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .help("Print the backtrace of errors"),
+        )
+        .subcommand(MoveyConfig::command())
+        .subcommand(MoveyLogin::command())
+        .subcommand(MoveyLogout::command())
+        .subcommand(MoveyUpload::command())
+        .subcommand(MoveyPackage::command())
+        .subcommand(MoveyWhoami::command())
+        .subcommand(MoveyToken::command());
     // Get matches
     let cli_matches = cli_app.get_matches();
 
@@ -0,0 +1,566 @@
+use reqwest::blocking::{
+    multipart::{Form, Part},
+    Client, RequestBuilder, Response,
+};
+use reqwest::header::{AUTHORIZATION, RETRY_AFTER};
+use serde::Serialize;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::error::MoveyApiError;
+use crate::oauth::{
+    AuthorizationRequest, DeviceAuthorization, DeviceTokenPoll, DeviceTokenRequest,
+    RefreshTokenRequest, TokenRequest, TokenResponse, CLIENT_ID,
+};
+use crate::retry::{parse_retry_after, RetryPolicy, IDEMPOTENCY_KEY_HEADER};
+use crate::types::{ApiToken, CreatedApiToken, NewApiToken, UploadRequest, UploadResponse, User};
+
+/// Timeout of a whole request, including reading the response.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long before its expiry a token that cannot be refreshed is warned
+/// about.
+pub const EXPIRY_WARNING: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// a token expiring sooner is refreshed before the request, so that it does
+// not expire on the way
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Client of the Movey registry API at `base_url`.
+///
+/// Failed requests are retried according to its [`RetryPolicy`]. When the
+/// token has a refresh token, it is refreshed before it expires, or once
+/// when the registry rejects it, and the request is sent again. Clones of a
+/// client share the refreshed token.
+#[derive(Debug, Clone)]
+pub struct MoveyClient {
+    base_url: String,
+    auth: Arc<Mutex<TokenState>>,
+    on_token_refresh: Option<RefreshHook>,
+    on_event: Option<EventHook>,
+    user_agent: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    retry_policy: RetryPolicy,
+    // built from the settings above, and shared by every request so that
+    // its connections are reused
+    http: Client,
+}
+
+// the token sent by the client, which changes when it is refreshed
+#[derive(Debug, Clone, Default)]
+struct TokenState {
+    token: Option<String>,
+    expires_at: Option<SystemTime>,
+    refresh_token: Option<String>,
+    expiry_warned: bool,
+}
+
+// called with every refreshed token
+#[derive(Clone)]
+struct RefreshHook(Arc<dyn Fn(&TokenResponse) + Send + Sync>);
+
+impl fmt::Debug for RefreshHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RefreshHook")
+    }
+}
+
+// called with every event
+#[derive(Clone)]
+struct EventHook(Arc<dyn Fn(&ClientEvent) + Send + Sync>);
+
+impl fmt::Debug for EventHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EventHook")
+    }
+}
+
+/// Something the client did or noticed on its own, which the user may want
+/// to know about. Its `Display` is the message to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientEvent {
+    /// A request failed with `reason`, the status or the transport error,
+    /// and is sent again after `delay`, as the `retry`th of `max_retries`.
+    Retrying {
+        reason: String,
+        delay: Duration,
+        retry: u32,
+        max_retries: u32,
+    },
+    /// The token, which cannot be refreshed, expires in `remaining`, zero
+    /// once it has expired. Reported once per client.
+    TokenExpiring { remaining: Duration },
+}
+
+impl fmt::Display for ClientEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientEvent::Retrying {
+                reason,
+                delay,
+                retry,
+                max_retries,
+            } => write!(
+                f,
+                "Request failed with {}, retrying in {:.1}s ({} of {})",
+                reason,
+                delay.as_secs_f64(),
+                retry,
+                max_retries
+            ),
+            ClientEvent::TokenExpiring { remaining } if remaining.is_zero() => {
+                f.write_str("the API token has expired, login again to get a new one")
+            }
+            ClientEvent::TokenExpiring { remaining } => write!(
+                f,
+                "the API token expires in {}, login again to get a new one",
+                format_remaining(*remaining)
+            ),
+        }
+    }
+}
+
+impl MoveyClient {
+    /// # Panics
+    ///
+    /// Like [`Client::new`], if the TLS backend cannot be initialized.
+    pub fn new(base_url: &str) -> Self {
+        let user_agent = format!("movey-client/{}", env!("CARGO_PKG_VERSION"));
+        MoveyClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth: Arc::default(),
+            on_token_refresh: None,
+            on_event: None,
+            http: build_http(&user_agent, DEFAULT_TIMEOUT, DEFAULT_CONNECT_TIMEOUT),
+            user_agent,
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// API token sent in the `Authorization` header.
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        let token = token.into();
+        self.with_auth(|auth| auth.token = Some(token))
+    }
+
+    /// When the token expires, to warn about it or refresh it in time.
+    pub fn with_token_expiry(self, expires_at: SystemTime) -> Self {
+        self.with_auth(|auth| auth.expires_at = Some(expires_at))
+    }
+
+    /// Token to get a new token from the registry when it expires.
+    pub fn with_refresh_token(self, refresh_token: impl Into<String>) -> Self {
+        let refresh_token = refresh_token.into();
+        self.with_auth(|auth| auth.refresh_token = Some(refresh_token))
+    }
+
+    /// Call `hook` with every refreshed token, to save it. The response
+    /// carries the refresh token still in use, even when the registry did
+    /// not issue a new one.
+    pub fn on_token_refresh(
+        mut self,
+        hook: impl Fn(&TokenResponse) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_token_refresh = Some(RefreshHook(Arc::new(hook)));
+        self
+    }
+
+    /// Call `hook` with every [`ClientEvent`], to report it. Nothing is
+    /// reported without a hook.
+    pub fn on_event(mut self, hook: impl Fn(&ClientEvent) + Send + Sync + 'static) -> Self {
+        self.on_event = Some(EventHook(Arc::new(hook)));
+        self
+    }
+
+    fn report(&self, event: ClientEvent) {
+        if let Some(EventHook(hook)) = &self.on_event {
+            hook(&event);
+        }
+    }
+
+    // the state is copied, so that clones made before are left unchanged
+    fn with_auth(mut self, update: impl FnOnce(&mut TokenState)) -> Self {
+        let mut auth = self.auth.lock().unwrap().clone();
+        update(&mut auth);
+        self.auth = Arc::new(Mutex::new(auth));
+        self
+    }
+
+    /// The token currently sent, which changes when it is refreshed.
+    pub fn token(&self) -> Option<String> {
+        self.auth.lock().unwrap().token.clone()
+    }
+
+    /// # Panics
+    ///
+    /// If `user_agent` is not a valid header value.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self.rebuild_http()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self.rebuild_http()
+    }
+
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self.rebuild_http()
+    }
+
+    fn rebuild_http(mut self) -> Self {
+        self.http = build_http(&self.user_agent, self.timeout, self.connect_timeout);
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Upload a package version to `/api/v1/packages/upload`.
+    pub fn upload<M: Serialize>(
+        &self,
+        request: &UploadRequest<M>,
+    ) -> Result<UploadResponse, MoveyApiError> {
+        let metadata = serde_json::to_string(&request.metadata)?;
+        let response = self.send(|client| {
+            let form = Form::new()
+                .part(
+                    "metadata",
+                    Part::text(metadata.clone()).mime_str("application/json")?,
+                )
+                .part(
+                    "archive",
+                    Part::bytes(request.archive.clone())
+                        .file_name(request.archive_file_name.clone())
+                        .mime_str("application/gzip")?,
+                );
+            let mut builder = client
+                .post(self.url("/api/v1/packages/upload"))
+                .multipart(form);
+            if let Some(key) = &request.idempotency_key {
+                builder = builder.header(IDEMPOTENCY_KEY_HEADER, key);
+            }
+            Ok(builder)
+        })?;
+        let slug = response.text()?;
+        Ok(UploadResponse {
+            url: format!("{}/packages/{}", self.base_url, slug),
+            slug,
+        })
+    }
+
+    /// The user owning the token, which checks that the token is valid.
+    pub fn whoami(&self) -> Result<User, MoveyApiError> {
+        let response = self.send(|client| Ok(client.get(self.url("/api/v1/whoami"))))?;
+        Ok(response.json()?)
+    }
+
+    /// Revoke the token used by the client, which cannot be used after.
+    pub fn revoke_token(&self) -> Result<(), MoveyApiError> {
+        self.send(|client| Ok(client.delete(self.url("/api/v1/tokens/current"))))?;
+        Ok(())
+    }
+
+    /// The API tokens of the user owning the token.
+    pub fn list_tokens(&self) -> Result<Vec<ApiToken>, MoveyApiError> {
+        let response = self.send(|client| Ok(client.get(self.url("/api/v1/tokens"))))?;
+        Ok(response.json()?)
+    }
+
+    /// Create an API token for the user owning the token.
+    pub fn create_token(&self, request: &NewApiToken) -> Result<CreatedApiToken, MoveyApiError> {
+        let response =
+            self.send(|client| Ok(client.post(self.url("/api/v1/tokens")).json(request)))?;
+        Ok(response.json()?)
+    }
+
+    /// Revoke the API token `id` of the user owning the token.
+    pub fn revoke_token_by_id(&self, id: u64) -> Result<(), MoveyApiError> {
+        let path = format!("/api/v1/tokens/{}", id);
+        self.send(|client| Ok(client.delete(self.url(&path))))?;
+        Ok(())
+    }
+
+    /// Page of the registry where the user authorizes the CLI, which then
+    /// redirects the browser to `request.redirect_uri` with a code.
+    pub fn authorize_url(&self, request: &AuthorizationRequest) -> String {
+        format!("{}?{}", self.url("/oauth/authorize"), request.query())
+    }
+
+    /// Exchange the code of an authorization for an API token.
+    pub fn exchange_code(&self, request: &TokenRequest) -> Result<TokenResponse, MoveyApiError> {
+        let response =
+            self.send(|client| Ok(client.post(self.url("/api/v1/oauth/token")).json(request)))?;
+        Ok(response.json()?)
+    }
+
+    /// Start a device authorization, which the user completes on another
+    /// machine.
+    pub fn request_device_code(&self) -> Result<DeviceAuthorization, MoveyApiError> {
+        let body = serde_json::json!({ "client_id": CLIENT_ID });
+        let response = self.send(|client| {
+            Ok(client
+                .post(self.url("/api/v1/oauth/device/code"))
+                .json(&body))
+        })?;
+        Ok(response.json()?)
+    }
+
+    /// Get a new token with `refresh_token`, without sending the current
+    /// token.
+    pub fn exchange_refresh_token(
+        &self,
+        refresh_token: &str,
+    ) -> Result<TokenResponse, MoveyApiError> {
+        let request = RefreshTokenRequest::new(refresh_token);
+        let response = self.send_with_token(None, &mut |client| {
+            Ok(client.post(self.url("/api/v1/oauth/token")).json(&request))
+        })?;
+        Ok(response.json()?)
+    }
+
+    /// Ask once for the token of the device authorization of `device_code`.
+    /// Denied and expired authorizations are errors.
+    pub fn poll_device_token(&self, device_code: &str) -> Result<DeviceTokenPoll, MoveyApiError> {
+        let request = DeviceTokenRequest::new(device_code);
+        let result =
+            self.send(|client| Ok(client.post(self.url("/api/v1/oauth/token")).json(&request)));
+        match result {
+            Ok(response) => Ok(DeviceTokenPoll::Token(response.json()?)),
+            // RFC 8628 answers 400 with the state of the authorization in `error`
+            Err(MoveyApiError::Rejected { message, .. }) if message == "authorization_pending" => {
+                Ok(DeviceTokenPoll::Pending)
+            }
+            Err(MoveyApiError::Rejected { message, .. }) if message == "slow_down" => {
+                Ok(DeviceTokenPoll::SlowDown)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    // send the request built by `request` with the token, refreshing it
+    // before it expires or when it is rejected
+    fn send<F>(&self, mut request: F) -> Result<Response, MoveyApiError>
+    where
+        F: FnMut(&Client) -> reqwest::Result<RequestBuilder>,
+    {
+        let mut refreshed = false;
+        if self.token_needs_refresh(SystemTime::now()) {
+            self.refresh()?;
+            refreshed = true;
+        }
+        loop {
+            let token = self.token();
+            match self.send_with_token(token.as_deref(), &mut request) {
+                // the token may have been revoked or expired early, a new
+                // one is tried once
+                Err(MoveyApiError::AuthFailed { status: 401, .. })
+                    if !refreshed && self.can_refresh() =>
+                {
+                    self.refresh()?;
+                    refreshed = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn can_refresh(&self) -> bool {
+        self.auth.lock().unwrap().refresh_token.is_some()
+    }
+
+    // whether the token must be refreshed before sending a request at
+    // `now`, reporting once a token which cannot be and expires soon
+    fn token_needs_refresh(&self, now: SystemTime) -> bool {
+        let mut auth = self.auth.lock().unwrap();
+        let remaining = match auth.expires_at {
+            Some(expires_at) if auth.token.is_some() => {
+                expires_at.duration_since(now).unwrap_or_default()
+            }
+            _ => return false,
+        };
+        if auth.refresh_token.is_some() {
+            return remaining <= REFRESH_MARGIN;
+        }
+        if remaining <= EXPIRY_WARNING && !auth.expiry_warned {
+            auth.expiry_warned = true;
+            drop(auth);
+            self.report(ClientEvent::TokenExpiring { remaining });
+        }
+        false
+    }
+
+    // replace the token by a new one, given to the refresh hook
+    fn refresh(&self) -> Result<(), MoveyApiError> {
+        let refresh_token = match self.auth.lock().unwrap().refresh_token.clone() {
+            Some(refresh_token) => refresh_token,
+            None => return Ok(()),
+        };
+        let mut response = match self.exchange_refresh_token(&refresh_token) {
+            Ok(response) => response,
+            Err(
+                MoveyApiError::Rejected { status, .. } | MoveyApiError::AuthFailed { status, .. },
+            ) => {
+                return Err(MoveyApiError::AuthFailed {
+                    status,
+                    message: String::from(
+                        "the API token expired and could not be refreshed, login again",
+                    ),
+                })
+            }
+            Err(error) => return Err(error),
+        };
+        response.refresh_token.get_or_insert(refresh_token);
+        {
+            let mut auth = self.auth.lock().unwrap();
+            auth.token = Some(response.token.clone());
+            auth.expires_at = response
+                .expires_in
+                .map(|expires_in| SystemTime::now() + Duration::from_secs(expires_in));
+            auth.refresh_token = response.refresh_token.clone();
+        }
+        if let Some(RefreshHook(hook)) = &self.on_token_refresh {
+            hook(&response);
+        }
+        Ok(())
+    }
+
+    // send the request built by `request` with the retry policy, turning
+    // unsuccessful responses into errors
+    fn send_with_token(
+        &self,
+        token: Option<&str>,
+        request: &mut dyn FnMut(&Client) -> reqwest::Result<RequestBuilder>,
+    ) -> Result<Response, MoveyApiError> {
+        let response = self.retry_policy.send(
+            &self.http,
+            || {
+                let builder = request(&self.http)?;
+                Ok(match token {
+                    Some(token) => builder.header(AUTHORIZATION, format!("Bearer {}", token)),
+                    None => builder,
+                })
+            },
+            |event| self.report(event),
+        )?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        if !status.is_client_error() && !status.is_server_error() {
+            return Err(MoveyApiError::UnexpectedStatus {
+                status: status.as_u16(),
+            });
+        }
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        Err(MoveyApiError::from_response(
+            status.as_u16(),
+            &response.text()?,
+            retry_after,
+        ))
+    }
+}
+
+// HTTP client sending the requests of a `MoveyClient`; it only fails to
+// build for a user agent which is not a header value, or when the TLS
+// backend cannot be initialized
+fn build_http(user_agent: &str, timeout: Duration, connect_timeout: Duration) -> Client {
+    Client::builder()
+        .user_agent(user_agent)
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .build()
+        .expect("could not build the HTTP client")
+}
+
+// `remaining` in the largest unit, like `3 days`
+fn format_remaining(remaining: Duration) -> String {
+    let seconds = remaining.as_secs();
+    let (count, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_needs_refresh_only_close_to_the_expiry() {
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+        let client = MoveyClient::new("https://www.movey.net")
+            .with_token("token")
+            .with_token_expiry(now + hour);
+        assert!(!client.token_needs_refresh(now));
+        assert!(client.auth.lock().unwrap().expiry_warned);
+
+        let client = client.with_refresh_token("refresh-token");
+        assert!(!client.token_needs_refresh(now));
+        assert!(client.token_needs_refresh(now + hour - REFRESH_MARGIN));
+        assert!(client.token_needs_refresh(now + hour * 2));
+    }
+
+    #[test]
+    fn token_expiring_is_reported_once() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let reported = events.clone();
+        let now = SystemTime::now();
+        let client = MoveyClient::new("https://www.movey.net")
+            .with_token("token")
+            .with_token_expiry(now)
+            .on_event(move |event| reported.lock().unwrap().push(event.clone()));
+        client.token_needs_refresh(now);
+        client.token_needs_refresh(now);
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            [ClientEvent::TokenExpiring {
+                remaining: Duration::ZERO
+            }]
+        );
+        assert_eq!(
+            events[0].to_string(),
+            "the API token has expired, login again to get a new one"
+        );
+    }
+
+    #[test]
+    fn format_remaining_uses_the_largest_unit() {
+        assert_eq!(format_remaining(Duration::from_secs(1)), "1 second");
+        assert_eq!(format_remaining(Duration::from_secs(150)), "2 minutes");
+        assert_eq!(format_remaining(Duration::from_secs(3600)), "1 hour");
+        assert_eq!(
+            format_remaining(Duration::from_secs(3 * 86400 - 1)),
+            "2 days"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::{fmt, time::Duration};
+use thiserror::Error;
+
+use crate::types::{ErrorResponse, FieldError};
+
+/// Errors returned by [`MoveyClient`](crate::MoveyClient).
+///
+/// Error responses of the registry are classified by their status and, when
+/// the body is a JSON [`ErrorResponse`], by its `code`.
+#[derive(Debug, Error)]
+pub enum MoveyApiError {
+    /// The API token is missing, invalid or not allowed to do the request.
+    #[error("{message}")]
+    AuthFailed { status: u16, message: String },
+    /// This version of the package was already published.
+    #[error("{message}")]
+    VersionExists { status: u16, message: String },
+    /// Some values of the request are invalid.
+    #[error("{message}{}", FieldErrors(errors))]
+    Validation {
+        status: u16,
+        message: String,
+        errors: Vec<FieldError>,
+    },
+    /// Too many requests were sent, even after retrying.
+    #[error("{message}")]
+    RateLimited {
+        status: u16,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// The registry refused the request with another 4xx status.
+    #[error("{message}")]
+    Rejected { status: u16, message: String },
+    /// The registry failed to handle the request with a 5xx status.
+    #[error("the registry failed with status {status}")]
+    Server { status: u16, message: String },
+    /// The registry responded with a status the client does not expect.
+    #[error("unexpected response status {status}")]
+    UnexpectedStatus { status: u16 },
+    /// The request could not be sent or the response could not be read.
+    #[error("could not reach the registry: {0}")]
+    Transport(#[from] reqwest::Error),
+    /// The request could not be encoded.
+    #[error("invalid request: {0}")]
+    InvalidRequest(#[from] serde_json::Error),
+}
+
+impl MoveyApiError {
+    /// Classify an error response with a 4xx or 5xx `status`. The body is
+    /// used as the message when it is not a JSON [`ErrorResponse`].
+    pub fn from_response(status: u16, body: &str, retry_after: Option<Duration>) -> Self {
+        let (response, message) = match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(response) => {
+                let message = response.message.clone().unwrap_or_default();
+                (response, message)
+            }
+            Err(_) => (ErrorResponse::default(), body.trim().to_string()),
+        };
+        let code = response.code.as_deref().unwrap_or_default();
+
+        if status >= 500 {
+            return MoveyApiError::Server { status, message };
+        }
+        if status == 401 || status == 403 || code == "unauthorized" || code == "forbidden" {
+            let message = default_message(message, "the API token was rejected");
+            MoveyApiError::AuthFailed { status, message }
+        } else if status == 409 || code == "version_exists" {
+            let message = default_message(message, "this version was already published");
+            MoveyApiError::VersionExists { status, message }
+        } else if status == 422 || code == "validation_failed" || !response.errors.is_empty() {
+            let message = default_message(message, "the package is invalid");
+            MoveyApiError::Validation {
+                status,
+                message,
+                errors: response.errors,
+            }
+        } else if status == 429 || code == "rate_limited" {
+            let message = default_message(message, "too many requests, try again later");
+            MoveyApiError::RateLimited {
+                status,
+                message,
+                retry_after,
+            }
+        } else if status >= 400 {
+            MoveyApiError::Rejected { status, message }
+        } else {
+            MoveyApiError::UnexpectedStatus { status }
+        }
+    }
+
+    /// HTTP status of the response, if the registry responded.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            MoveyApiError::AuthFailed { status, .. }
+            | MoveyApiError::VersionExists { status, .. }
+            | MoveyApiError::Validation { status, .. }
+            | MoveyApiError::RateLimited { status, .. }
+            | MoveyApiError::Rejected { status, .. }
+            | MoveyApiError::Server { status, .. }
+            | MoveyApiError::UnexpectedStatus { status } => Some(*status),
+            MoveyApiError::Transport(error) => error.status().map(|status| status.as_u16()),
+            MoveyApiError::InvalidRequest(_) => None,
+        }
+    }
+}
+
+fn default_message(message: String, default: &str) -> String {
+    if message.is_empty() {
+        default.to_string()
+    } else {
+        message
+    }
+}
+
+// one line per invalid field, after the message
+struct FieldErrors<'a>(&'a [FieldError]);
+
+impl fmt::Display for FieldErrors<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for error in self.0 {
+            write!(f, "\n  {}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_response_works_with_json_bodies() {
+        let error = MoveyApiError::from_response(
+            400,
+            r#"{"code": "version_exists", "message": "Package1 0.1.0 exists"}"#,
+            None,
+        );
+        assert!(matches!(
+            error,
+            MoveyApiError::VersionExists { status: 400, .. }
+        ));
+        assert_eq!(error.to_string(), "Package1 0.1.0 exists");
+
+        let error = MoveyApiError::from_response(
+            400,
+            r#"{"error": "invalid package", "errors": [
+                {"path": "package.version", "message": "must be semver"},
+                {"field": "repository_url", "message": "is not reachable"}
+            ]}"#,
+            None,
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid package\n  package.version: must be semver\n  \
+            repository_url: is not reachable"
+        );
+    }
+
+    #[test]
+    fn from_response_works_with_statuses() {
+        let error = MoveyApiError::from_response(401, "Invalid Api token", None);
+        assert!(matches!(
+            error,
+            MoveyApiError::AuthFailed { status: 401, .. }
+        ));
+        assert_eq!(error.to_string(), "Invalid Api token");
+
+        let error = MoveyApiError::from_response(409, "", None);
+        assert_eq!(error.to_string(), "this version was already published");
+
+        let error = MoveyApiError::from_response(422, "{}", None);
+        assert!(matches!(&error, MoveyApiError::Validation { errors, .. } if errors.is_empty()));
+        assert_eq!(error.to_string(), "the package is invalid");
+
+        let error = MoveyApiError::from_response(429, "", Some(Duration::from_secs(5)));
+        assert!(matches!(
+            error,
+            MoveyApiError::RateLimited {
+                retry_after: Some(_),
+                ..
+            }
+        ));
+
+        let error = MoveyApiError::from_response(400, "Bad request", None);
+        assert!(matches!(error, MoveyApiError::Rejected { status: 400, .. }));
+
+        let error = MoveyApiError::from_response(503, r#"{"code": "unauthorized"}"#, None);
+        assert!(matches!(error, MoveyApiError::Server { status: 503, .. }));
+    }
+}
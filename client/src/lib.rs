@@ -0,0 +1,30 @@
+//! Typed client for the Movey registry API.
+//!
+//! ```no_run
+//! use movey_client::{MoveyClient, UploadRequest};
+//!
+//! let client = MoveyClient::new("https://www.movey.net").with_token("api-token");
+//! let response = client.upload(&UploadRequest {
+//!     metadata: serde_json::json!({ "package": { "name": "Package1" } }),
+//!     archive_file_name: String::from("Package1-0.1.0.tar.gz"),
+//!     archive: vec![],
+//!     idempotency_key: None,
+//! })?;
+//! println!("{}", response.url);
+//! # Ok::<(), movey_client::MoveyApiError>(())
+//! ```
+
+mod client;
+pub mod error;
+pub mod oauth;
+pub mod retry;
+pub mod scope;
+pub mod types;
+
+pub use client::{ClientEvent, MoveyClient, DEFAULT_TIMEOUT};
+pub use error::MoveyApiError;
+pub use retry::RetryPolicy;
+pub use types::{
+    ApiToken, CreatedApiToken, ErrorResponse, FieldError, NewApiToken, UploadRequest,
+    UploadResponse, User,
+};
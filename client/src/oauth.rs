@@ -0,0 +1,256 @@
+//! Pieces of the OAuth 2.0 flows used to login: the authorization code flow
+//! with PKCE (RFC 7636) from a browser, and the device authorization flow
+//! (RFC 8628) from machines without one.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::form_urlencoded;
+
+/// Identifier of the CLI on the registry.
+pub const CLIENT_ID: &str = "movey-cli";
+/// Only the SHA-256 challenge method is used, the plain one is not safe.
+pub const CODE_CHALLENGE_METHOD: &str = "S256";
+/// Grant type exchanging a device code for a token.
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+// seconds between two polls when the registry does not say
+const DEFAULT_POLL_INTERVAL: u64 = 5;
+
+/// Proof key of one authorization: the registry is given the challenge, and
+/// only who knows the verifier can exchange the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    /// A random verifier and its challenge.
+    pub fn generate() -> Result<Self, getrandom::Error> {
+        Ok(Pkce::from_verifier(random_token()?))
+    }
+
+    pub fn from_verifier(verifier: impl Into<String>) -> Self {
+        let verifier = verifier.into();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Pkce {
+            verifier,
+            challenge,
+        }
+    }
+}
+
+/// A random url safe string with 256 bits of entropy, for verifiers and
+/// states.
+pub fn random_token() -> Result<String, getrandom::Error> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Parameters of the page where the user authorizes the CLI.
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest<'a> {
+    /// Where the browser is redirected with the code.
+    pub redirect_uri: &'a str,
+    /// Random value the redirect must carry back, see [`random_token`].
+    pub state: &'a str,
+    pub code_challenge: &'a str,
+}
+
+impl AuthorizationRequest<'_> {
+    /// Query string of the authorization page.
+    pub fn query(&self) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", CLIENT_ID)
+            .append_pair("redirect_uri", self.redirect_uri)
+            .append_pair("state", self.state)
+            .append_pair("code_challenge", self.code_challenge)
+            .append_pair("code_challenge_method", CODE_CHALLENGE_METHOD)
+            .finish()
+    }
+}
+
+/// Query parameters of the redirect back from the authorization page,
+/// either a `code` or an `error`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthorizationResponse {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+impl AuthorizationResponse {
+    pub fn from_query(query: &str) -> Self {
+        let mut response = AuthorizationResponse::default();
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            let value = Some(value.into_owned());
+            match key.as_ref() {
+                "code" => response.code = value,
+                "state" => response.state = value,
+                "error" => response.error = value,
+                "error_description" => response.error_description = value,
+                _ => {}
+            }
+        }
+        response
+    }
+}
+
+/// Body of `POST /api/v1/oauth/token`, exchanging a code for a token.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenRequest<'a> {
+    pub grant_type: &'a str,
+    pub client_id: &'a str,
+    pub code: &'a str,
+    pub code_verifier: &'a str,
+    pub redirect_uri: &'a str,
+}
+
+impl<'a> TokenRequest<'a> {
+    pub fn authorization_code(
+        code: &'a str,
+        code_verifier: &'a str,
+        redirect_uri: &'a str,
+    ) -> Self {
+        TokenRequest {
+            grant_type: "authorization_code",
+            client_id: CLIENT_ID,
+            code,
+            code_verifier,
+            redirect_uri,
+        }
+    }
+}
+
+/// Body of `POST /api/v1/oauth/token`, polling for the token of a device
+/// authorization.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceTokenRequest<'a> {
+    pub grant_type: &'a str,
+    pub client_id: &'a str,
+    pub device_code: &'a str,
+}
+
+impl<'a> DeviceTokenRequest<'a> {
+    pub fn new(device_code: &'a str) -> Self {
+        DeviceTokenRequest {
+            grant_type: DEVICE_CODE_GRANT_TYPE,
+            client_id: CLIENT_ID,
+            device_code,
+        }
+    }
+}
+
+/// Body of `POST /api/v1/oauth/token`, renewing an expired token.
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshTokenRequest<'a> {
+    pub grant_type: &'a str,
+    pub client_id: &'a str,
+    pub refresh_token: &'a str,
+}
+
+impl<'a> RefreshTokenRequest<'a> {
+    pub fn new(refresh_token: &'a str) -> Self {
+        RefreshTokenRequest {
+            grant_type: "refresh_token",
+            client_id: CLIENT_ID,
+            refresh_token,
+        }
+    }
+}
+
+/// API token issued by `POST /api/v1/oauth/token`.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct TokenResponse {
+    #[serde(alias = "access_token")]
+    pub token: String,
+    /// Seconds until the token expires, if it does.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    /// Token to get a new token once this one expires.
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// Scopes of the token, separated by spaces.
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl TokenResponse {
+    /// The scopes of the token, if the registry gave them.
+    pub fn scopes(&self) -> Option<Vec<String>> {
+        let scope = self.scope.as_deref()?;
+        Some(scope.split_whitespace().map(str::to_string).collect())
+    }
+}
+
+/// Device authorization started by `POST /api/v1/oauth/device/code`: the
+/// user enters `user_code` on `verification_uri` while the CLI polls for
+/// the token with `device_code`.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// `verification_uri` with the user code filled in.
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /// Seconds until the codes expire.
+    pub expires_in: u64,
+    /// Seconds to wait between two polls.
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    DEFAULT_POLL_INTERVAL
+}
+
+/// Answer to a poll for the token of a device authorization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceTokenPoll {
+    /// The user authorized the device.
+    Token(TokenResponse),
+    /// The user did not answer yet, poll again after the interval.
+    Pending,
+    /// Polls are too frequent, the interval must grow by 5 seconds.
+    SlowDown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_is_the_sha256_of_the_verifier() {
+        // example of RFC 7636, appendix B
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        assert_eq!(
+            pkce.challenge,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+
+        let pkce = Pkce::generate().unwrap();
+        assert_eq!(pkce.verifier.len(), 43);
+        assert_ne!(pkce, Pkce::generate().unwrap());
+    }
+
+    #[test]
+    fn authorization_query_round_trips() {
+        let request = AuthorizationRequest {
+            redirect_uri: "http://127.0.0.1:8080/callback",
+            state: "a state",
+            code_challenge: "challenge",
+        };
+        let query = request.query();
+        assert!(query.contains("redirect_uri=http%3A%2F%2F127.0.0.1%3A8080%2Fcallback"));
+        assert!(query.contains("code_challenge_method=S256"));
+
+        let response = AuthorizationResponse::from_query("code=abc&state=a+state&other=1");
+        assert_eq!(response.code.as_deref(), Some("abc"));
+        assert_eq!(response.state.as_deref(), Some("a state"));
+        assert_eq!(response.error, None);
+    }
+}
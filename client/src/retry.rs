@@ -0,0 +1,243 @@
+use reqwest::blocking::{Client, Request, RequestBuilder, Response};
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use sha2::{Digest, Sha256};
+
+use crate::client::ClientEvent;
+use std::{
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Header that lets the registry recognize a retried request, so that
+/// retrying an upload cannot publish the same version twice.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// How requests are retried after a transport error, a 429 or a 5xx.
+///
+/// Only requests which can safely be sent twice are retried: those with an
+/// idempotent method, and those carrying an [`IDEMPOTENCY_KEY_HEADER`].
+///
+/// The delay before retry `n` is picked at random between half and all of
+/// `base_delay * 2^n`, capped at `max_delay`. A 429 or 503 with a
+/// `Retry-After` header waits the requested time instead, unless it is
+/// longer than `max_delay`, in which case the response is returned as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_max_retries(max_retries: u32) -> Self {
+        RetryPolicy {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Send the request built by `request` with `client`. `request` is called
+    /// again for every attempt since bodies like multipart forms cannot be
+    /// cloned, and the first response that is not retried is returned. Every
+    /// retry is reported to `on_event` beforehand.
+    pub fn send<F, E>(
+        &self,
+        client: &Client,
+        mut request: F,
+        mut on_event: E,
+    ) -> reqwest::Result<Response>
+    where
+        F: FnMut() -> reqwest::Result<RequestBuilder>,
+        E: FnMut(ClientEvent),
+    {
+        let mut retry = 0;
+        loop {
+            let mut retryable = false;
+            let result = request()
+                .and_then(RequestBuilder::build)
+                .and_then(|request| {
+                    retryable = is_retryable_request(&request);
+                    client.execute(request)
+                });
+            let delay = match &result {
+                _ if !retryable || retry >= self.max_retries => None,
+                Ok(response) => self.response_delay(retry, response),
+                Err(error) if error.is_builder() => None,
+                Err(_) => Some(self.backoff(retry)),
+            };
+            let delay = match delay {
+                Some(delay) => delay,
+                None => return result,
+            };
+            retry += 1;
+            let reason = match &result {
+                Ok(response) => format!("status {}", response.status().as_u16()),
+                Err(error) => error.to_string(),
+            };
+            on_event(ClientEvent::Retrying {
+                reason,
+                delay,
+                retry,
+                max_retries: self.max_retries,
+            });
+            thread::sleep(delay);
+        }
+    }
+
+    // delay before retrying after `response`, or None if it is final
+    fn response_delay(&self, retry: u32, response: &Response) -> Option<Duration> {
+        let status = response.status();
+        if !is_retryable_status(status) {
+            return None;
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, SystemTime::now()));
+            if let Some(retry_after) = retry_after {
+                return (retry_after <= self.max_delay).then_some(retry_after);
+            }
+        }
+        Some(self.backoff(retry))
+    }
+
+    /// Jittered exponential delay before retry number `retry + 1`.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        delay / 2 + (delay / 2).mul_f64(random_fraction())
+    }
+}
+
+/// Whether `request` can be sent again without side effects, because its
+/// method is idempotent or it carries an idempotency key.
+pub fn is_retryable_request(request: &Request) -> bool {
+    request.method().is_idempotent() || request.headers().contains_key(IDEMPOTENCY_KEY_HEADER)
+}
+
+/// Whether a response with `status` is worth retrying.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Parse a `Retry-After` value, either a number of seconds or an HTTP date.
+/// A date in the past means no wait.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// Idempotency key derived from `parts`, so that the same content always
+/// gets the same key, even across runs.
+pub fn idempotency_key(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// uniformly distributed in [0, 1), from the OS random number generator; the
+// delay is not jittered when it is unavailable
+fn random_fraction() -> f64 {
+    let mut bytes = [0u8; 8];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return 1.0;
+    }
+    // the 53 bits an f64 holds exactly
+    (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for (retry, max) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000)] {
+            let delay = policy.backoff(retry);
+            let max = Duration::from_millis(max);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+        assert!(policy.backoff(u32::MAX) <= policy.max_delay);
+    }
+
+    #[test]
+    fn parse_retry_after_works_with_seconds() {
+        let now = SystemTime::now();
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after("0", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("-1", now), None);
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn parse_retry_after_works_with_http_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn is_retryable_status_works() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::CONFLICT));
+        assert!(!is_retryable_status(StatusCode::NOT_IMPLEMENTED));
+    }
+
+    #[test]
+    fn idempotency_key_is_stable_and_unambiguous() {
+        assert_eq!(
+            idempotency_key(&["Package1", "0.0.0"]),
+            idempotency_key(&["Package1", "0.0.0"])
+        );
+        assert_ne!(
+            idempotency_key(&["Package1", "0.0.0"]),
+            idempotency_key(&["Package10", ".0.0"])
+        );
+        assert_eq!(idempotency_key(&[]).len(), 64);
+    }
+}
@@ -0,0 +1,11 @@
+//! Scopes of API tokens, which limit what the registry lets a token do. A
+//! CI token may for instance only have [`PUBLISH`].
+
+/// Upload packages.
+pub const PUBLISH: &str = "publish";
+/// Read the account, like its tokens.
+pub const READ: &str = "read";
+/// Create and revoke the tokens of the account.
+pub const TOKENS: &str = "tokens";
+/// Every scope a token may be given.
+pub const ALL: [&str; 3] = [PUBLISH, READ, TOKENS];
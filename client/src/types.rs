@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+/// Upload of a package version: its metadata, sent as JSON, and its source
+/// archive.
+#[derive(Debug, Clone)]
+pub struct UploadRequest<M: Serialize> {
+    pub metadata: M,
+    pub archive_file_name: String,
+    pub archive: Vec<u8>,
+    /// Sent as the `Idempotency-Key` header, so that the registry does not
+    /// publish the same upload twice when it is retried.
+    pub idempotency_key: Option<String>,
+}
+
+/// Package version published by an upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadResponse {
+    /// Identifier of the package on the registry.
+    pub slug: String,
+    /// Page of the package on the registry.
+    pub url: String,
+}
+
+/// Owner of the API token, returned by `GET /api/v1/whoami`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct User {
+    pub username: String,
+    /// Scopes of the token, if the registry gives them.
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+}
+
+/// API token of the user, as listed by `GET /api/v1/tokens`. The token
+/// itself is only returned once, when it is created.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ApiToken {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// `None` for tokens which do not expire.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+}
+
+/// Body of `POST /api/v1/tokens`, creating a token.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewApiToken<'a> {
+    pub name: &'a str,
+    pub scopes: &'a [String],
+    /// Seconds until the token expires, it does not expire without them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+}
+
+/// Token created by `POST /api/v1/tokens`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+/// JSON body of an error response of the registry. Every field is optional:
+///
+/// ```json
+/// {
+///   "code": "validation_failed",
+///   "message": "invalid package",
+///   "errors": [{ "field": "package.version", "message": "must be semver" }]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct ErrorResponse {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default, alias = "error")]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub errors: Vec<FieldError>,
+}
+
+/// Invalid value of a request, identified by its path like `package.version`.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct FieldError {
+    #[serde(alias = "path")]
+    pub field: String,
+    pub message: String,
+}
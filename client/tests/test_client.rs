@@ -0,0 +1,471 @@
+use httpmock::{
+    Method::{DELETE, GET, POST},
+    MockServer,
+};
+use movey_client::oauth::{AuthorizationRequest, DeviceTokenPoll, TokenRequest, TokenResponse};
+use movey_client::{MoveyApiError, MoveyClient, NewApiToken, RetryPolicy, UploadRequest};
+use serde_json::json;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+fn upload_request() -> UploadRequest<serde_json::Value> {
+    UploadRequest {
+        metadata: json!({ "package": { "name": "Package1", "version": "0.1.0" } }),
+        archive_file_name: String::from("Package1-0.1.0.tar.gz"),
+        archive: b"archive".to_vec(),
+        idempotency_key: Some(String::from("key")),
+    }
+}
+
+#[test]
+fn upload_works() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/v1/packages/upload")
+            .header("authorization", "Bearer test-token")
+            .header("user-agent", "test-agent")
+            .header("idempotency-key", "key")
+            .body_contains("{\"package\":{\"name\":\"Package1\",\"version\":\"0.1.0\"}}")
+            .body_contains("filename=\"Package1-0.1.0.tar.gz\"");
+        then.status(200).body("package1");
+    });
+
+    let client = MoveyClient::new(&format!("{}/", server.base_url()))
+        .with_token("test-token")
+        .with_user_agent("test-agent");
+    let response = client.upload(&upload_request()).unwrap();
+
+    mock.assert();
+    assert_eq!(response.slug, "package1");
+    assert_eq!(
+        response.url,
+        format!("{}/packages/package1", server.base_url())
+    );
+}
+
+#[test]
+fn upload_fails_with_rejected_error_if_server_responds_4xx() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST).path("/api/v1/packages/upload");
+        then.status(400).body("Invalid package");
+    });
+
+    let error = MoveyClient::new(&server.base_url())
+        .upload(&upload_request())
+        .unwrap_err();
+
+    assert!(
+        matches!(&error, MoveyApiError::Rejected { status: 400, message } if message == "Invalid package"),
+        "{:?}",
+        error
+    );
+    assert_eq!(error.to_string(), "Invalid package");
+}
+
+#[test]
+fn upload_fails_with_typed_error_from_json_error_body() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST).path("/api/v1/packages/upload");
+        then.status(409)
+            .header("content-type", "application/json")
+            .json_body(json!({
+                "code": "version_exists",
+                "message": "Package1 0.1.0 was already published"
+            }));
+    });
+
+    let error = MoveyClient::new(&server.base_url())
+        .upload(&upload_request())
+        .unwrap_err();
+
+    assert!(
+        matches!(error, MoveyApiError::VersionExists { status: 409, .. }),
+        "{:?}",
+        error
+    );
+    assert_eq!(error.to_string(), "Package1 0.1.0 was already published");
+}
+
+#[test]
+fn upload_fails_with_server_error_once_retries_are_exhausted() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/api/v1/packages/upload");
+        then.status(503).header("Retry-After", "0");
+    });
+
+    let error = MoveyClient::new(&server.base_url())
+        .with_retry_policy(RetryPolicy::with_max_retries(2))
+        .upload(&upload_request())
+        .unwrap_err();
+
+    mock.assert_hits(3);
+    assert!(
+        matches!(error, MoveyApiError::Server { status: 503, .. }),
+        "{:?}",
+        error
+    );
+    assert_eq!(error.status(), Some(503));
+}
+
+#[test]
+fn upload_without_idempotency_key_is_not_retried() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/api/v1/packages/upload");
+        then.status(503).header("Retry-After", "0");
+    });
+
+    let error = MoveyClient::new(&server.base_url())
+        .with_retry_policy(RetryPolicy::with_max_retries(2))
+        .upload(&UploadRequest {
+            idempotency_key: None,
+            ..upload_request()
+        })
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert_eq!(error.status(), Some(503));
+}
+
+#[test]
+fn only_idempotent_requests_are_retried() {
+    let server = MockServer::start();
+    let whoami = server.mock(|when, then| {
+        when.method(GET).path("/api/v1/whoami");
+        then.status(503).header("Retry-After", "0");
+    });
+    let create = server.mock(|when, then| {
+        when.method(POST).path("/api/v1/tokens");
+        then.status(503).header("Retry-After", "0");
+    });
+    let client = MoveyClient::new(&server.base_url())
+        .with_token("test-token")
+        .with_retry_policy(RetryPolicy::with_max_retries(2));
+
+    client.whoami().unwrap_err();
+    client
+        .create_token(&NewApiToken {
+            name: "ci",
+            scopes: &[String::from("publish")],
+            expires_in: None,
+        })
+        .unwrap_err();
+
+    whoami.assert_hits(3);
+    create.assert_hits(1);
+}
+
+#[test]
+fn upload_fails_with_transport_error_if_server_is_unreachable() {
+    let error = MoveyClient::new("http://127.0.0.1:1")
+        .with_retry_policy(RetryPolicy::with_max_retries(0))
+        .upload(&upload_request())
+        .unwrap_err();
+
+    assert!(matches!(error, MoveyApiError::Transport(_)), "{:?}", error);
+    assert_eq!(error.status(), None);
+}
+
+#[test]
+fn whoami_works() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/api/v1/whoami")
+            .header("authorization", "Bearer test-token");
+        then.status(200).json_body(json!({ "username": "alice" }));
+    });
+
+    let user = MoveyClient::new(&server.base_url())
+        .with_token("test-token")
+        .whoami()
+        .unwrap();
+
+    mock.assert();
+    assert_eq!(user.username, "alice");
+}
+
+#[test]
+fn whoami_fails_with_auth_error_if_token_is_rejected() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(GET).path("/api/v1/whoami");
+        then.status(401)
+            .json_body(json!({ "message": "invalid token" }));
+    });
+
+    let error = MoveyClient::new(&server.base_url())
+        .with_token("bad-token")
+        .whoami()
+        .unwrap_err();
+
+    assert!(
+        matches!(error, MoveyApiError::AuthFailed { status: 401, .. }),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn revoke_token_works() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(DELETE)
+            .path("/api/v1/tokens/current")
+            .header("authorization", "Bearer test-token");
+        then.status(204);
+    });
+
+    MoveyClient::new(&server.base_url())
+        .with_token("test-token")
+        .revoke_token()
+        .unwrap();
+
+    mock.assert();
+}
+
+#[test]
+fn token_management_works() {
+    let server = MockServer::start();
+    let list_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/api/v1/tokens")
+            .header("authorization", "Bearer test-token");
+        then.status(200).json_body(json!([
+            { "id": 1, "name": "laptop", "scopes": ["publish"], "expires_at": null },
+            { "id": 2, "name": "ci" }
+        ]));
+    });
+    let create_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/v1/tokens")
+            .json_body(json!({ "name": "ci", "scopes": ["publish"], "expires_in": 3600 }));
+        then.status(201).json_body(json!({
+            "id": 3,
+            "name": "ci",
+            "scopes": ["publish"],
+            "token": "new-token"
+        }));
+    });
+    let revoke_mock = server.mock(|when, then| {
+        when.method(DELETE).path("/api/v1/tokens/3");
+        then.status(204);
+    });
+    let client = MoveyClient::new(&server.base_url()).with_token("test-token");
+
+    let tokens = client.list_tokens().unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0].scopes, ["publish"]);
+    assert!(tokens[1].scopes.is_empty());
+    let created = client
+        .create_token(&NewApiToken {
+            name: "ci",
+            scopes: &[String::from("publish")],
+            expires_in: Some(3600),
+        })
+        .unwrap();
+    assert_eq!(created.api_token.id, 3);
+    assert_eq!(created.token, "new-token");
+    client.revoke_token_by_id(3).unwrap();
+
+    list_mock.assert();
+    create_mock.assert();
+    revoke_mock.assert();
+}
+
+#[test]
+fn exchange_code_works() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/v1/oauth/token")
+            .json_body(json!({
+                "grant_type": "authorization_code",
+                "client_id": "movey-cli",
+                "code": "test-code",
+                "code_verifier": "test-verifier",
+                "redirect_uri": "http://127.0.0.1:8080/callback",
+            }));
+        then.status(200).json_body(json!({ "token": "test-token" }));
+    });
+
+    let client = MoveyClient::new(&server.base_url());
+    let response = client
+        .exchange_code(&TokenRequest::authorization_code(
+            "test-code",
+            "test-verifier",
+            "http://127.0.0.1:8080/callback",
+        ))
+        .unwrap();
+
+    mock.assert();
+    assert_eq!(response.token, "test-token");
+    let authorize_url = client.authorize_url(&AuthorizationRequest {
+        redirect_uri: "http://127.0.0.1:8080/callback",
+        state: "test-state",
+        code_challenge: "test-challenge",
+    });
+    assert!(authorize_url.starts_with(&format!(
+        "{}/oauth/authorize?response_type=code&client_id=movey-cli&",
+        server.base_url()
+    )));
+}
+
+#[test]
+fn device_authorization_works() {
+    let server = MockServer::start();
+    let code_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/v1/oauth/device/code")
+            .json_body(json!({ "client_id": "movey-cli" }));
+        then.status(200).json_body(json!({
+            "device_code": "device-code",
+            "user_code": "WDJB-MJHT",
+            "verification_uri": "https://www.movey.net/device",
+            "expires_in": 900,
+        }));
+    });
+    let poll = |error: &'static str| {
+        server.mock(move |when, then| {
+            when.method(POST)
+                .path("/api/v1/oauth/token")
+                .json_body(json!({
+                    "grant_type": "urn:ietf:params:oauth:grant-type:device_code",
+                    "client_id": "movey-cli",
+                    "device_code": "device-code",
+                }));
+            if error.is_empty() {
+                then.status(200)
+                    .json_body(json!({ "access_token": "device-token", "expires_in": 3600 }));
+            } else {
+                then.status(400).json_body(json!({ "error": error }));
+            }
+        })
+    };
+    let client = MoveyClient::new(&server.base_url());
+
+    let authorization = client.request_device_code().unwrap();
+    code_mock.assert();
+    assert_eq!(authorization.user_code, "WDJB-MJHT");
+    assert_eq!(authorization.interval, 5);
+    assert_eq!(authorization.verification_uri_complete, None);
+
+    let mut mock = poll("authorization_pending");
+    assert_eq!(
+        client.poll_device_token("device-code").unwrap(),
+        DeviceTokenPoll::Pending
+    );
+    mock.delete();
+    mock = poll("slow_down");
+    assert_eq!(
+        client.poll_device_token("device-code").unwrap(),
+        DeviceTokenPoll::SlowDown
+    );
+    mock.delete();
+    mock = poll("access_denied");
+    let error = client.poll_device_token("device-code").unwrap_err();
+    assert!(matches!(error, MoveyApiError::Rejected { status: 400, .. }));
+    assert_eq!(error.to_string(), "access_denied");
+    mock.delete();
+    poll("");
+    assert_eq!(
+        client.poll_device_token("device-code").unwrap(),
+        DeviceTokenPoll::Token(TokenResponse {
+            token: String::from("device-token"),
+            expires_in: Some(3600),
+            ..TokenResponse::default()
+        })
+    );
+}
+
+#[test]
+fn expired_or_rejected_tokens_are_refreshed_once() {
+    let server = MockServer::start();
+    let refresh_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/v1/oauth/token")
+            .json_body(json!({
+                "grant_type": "refresh_token",
+                "client_id": "movey-cli",
+                "refresh_token": "refresh-token",
+            }))
+            .matches(|request| {
+                !request.headers.as_ref().is_some_and(|headers| {
+                    headers
+                        .iter()
+                        .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+                })
+            });
+        then.status(200).json_body(
+            json!({ "access_token": "new-token", "expires_in": 3600, "scope": "publish" }),
+        );
+    });
+    let old_whoami = server.mock(|when, then| {
+        when.method(GET)
+            .path("/api/v1/whoami")
+            .header("authorization", "Bearer old-token");
+        then.status(401);
+    });
+    let new_whoami = server.mock(|when, then| {
+        when.method(GET)
+            .path("/api/v1/whoami")
+            .header("authorization", "Bearer new-token");
+        then.status(200).json_body(json!({ "username": "alice" }));
+    });
+    let refreshed = Arc::new(Mutex::new(vec![]));
+    let client = |expires_at: SystemTime| {
+        let refreshed = refreshed.clone();
+        MoveyClient::new(&server.base_url())
+            .with_token("old-token")
+            .with_token_expiry(expires_at)
+            .with_refresh_token("refresh-token")
+            .on_token_refresh(move |response| refreshed.lock().unwrap().push(response.clone()))
+    };
+
+    // an expired token is refreshed before the request
+    let expired = client(SystemTime::now() - Duration::from_secs(1));
+    assert_eq!(expired.whoami().unwrap().username, "alice");
+    old_whoami.assert_hits(0);
+    refresh_mock.assert_hits(1);
+    assert_eq!(expired.token().as_deref(), Some("new-token"));
+
+    // a token rejected before its expiry is refreshed, and the request sent again
+    let rejected = client(SystemTime::now() + Duration::from_secs(3600));
+    assert_eq!(rejected.whoami().unwrap().username, "alice");
+    old_whoami.assert_hits(1);
+    refresh_mock.assert_hits(2);
+    new_whoami.assert_hits(2);
+
+    {
+        let refreshed = refreshed.lock().unwrap();
+        assert_eq!(refreshed.len(), 2);
+        assert_eq!(refreshed[0].token, "new-token");
+        assert_eq!(refreshed[0].refresh_token.as_deref(), Some("refresh-token"));
+        assert_eq!(refreshed[0].scopes(), Some(vec![String::from("publish")]));
+    }
+
+    // a token rejected after a refresh is not refreshed again
+    let mut new_whoami = new_whoami;
+    new_whoami.delete();
+    let revoked_whoami = server.mock(|when, then| {
+        when.method(GET)
+            .path("/api/v1/whoami")
+            .header("authorization", "Bearer new-token");
+        then.status(401);
+    });
+    let error = client(SystemTime::now() - Duration::from_secs(1))
+        .whoami()
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        MoveyApiError::AuthFailed { status: 401, .. }
+    ));
+    refresh_mock.assert_hits(3);
+    revoked_whoami.assert_hits(1);
+}
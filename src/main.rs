@@ -36,12 +36,19 @@ fn main() -> Result<()> {
     //
     //utils::logger::setup_logging()?;
     let _guard = slog_scope::set_global_logger(utils::logger::default_root_logger()?);
-    let _log_guard = slog_stdlog::init()?;
+    slog_stdlog::init()?;
 
     // Initialize Configuration
     let config_contents = include_str!("resources/default_config.toml");
     AppConfig::init(Some(config_contents))?;
 
     // Match Commands
-    cli::cli_match()
+    let result = cli::cli_match();
+    drop(_guard);
+    if let Err(error) = result {
+        let verbose = AppConfig::get::<bool>("debug").unwrap_or(false);
+        eprintln!("Error: {}", error.report(verbose));
+        std::process::exit(cli::exit_code::error_exit_code(&error));
+    }
+    Ok(())
 }
@@ -0,0 +1,2 @@
+#[test_only]
+module 0x1::DummyTests {}
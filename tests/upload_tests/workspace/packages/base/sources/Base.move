@@ -0,0 +1 @@
+module 0x1::Base {}
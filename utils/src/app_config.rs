@@ -32,7 +32,7 @@ impl AppConfig {
         // executable. Check include_str! for more info.
         if let Some(config_contents) = default_config {
             //let contents = include_str!(config_file_path);
-            settings.merge(config::File::from_str(&config_contents, config::FileFormat::Toml))?;
+            settings.merge(config::File::from_str(config_contents, config::FileFormat::Toml))?;
         }
 
         // Merge settings with env variables
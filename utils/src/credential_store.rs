@@ -0,0 +1,911 @@
+// Copyright (c) The Move Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+use toml_edit::easy::{map::Map, Value};
+
+use crate::movey_credential::{
+    read_credential_file, registry_names, registry_table, registry_table_mut, MOVEY_CREDENTIAL_PATH,
+};
+
+/// Environment variable holding the passphrase of the encrypted store.
+pub const MOVEY_CREDENTIAL_PASSPHRASE_ENV: &str = "MOVEY_CREDENTIAL_PASSPHRASE";
+/// File of the encrypted store, next to the credential file.
+pub const ENCRYPTED_CREDENTIAL_PATH: &str = "/movey_credential.enc";
+// table of the credential file selecting the store
+const CREDENTIAL_TABLE: &str = "credential";
+// format of the encrypted file, to be able to change it later
+const ENCRYPTED_FILE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// API token of a registry, with what is known about it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credential {
+    pub token: String,
+    /// When the token expires, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Token to get a new token once this one expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// What the token allows, if the registry said.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+}
+
+// keys of a credential in the table of a registry, the others are kept
+const CREDENTIAL_KEYS: [&str; 4] = ["token", "expires_at", "refresh_token", "scopes"];
+
+impl Credential {
+    /// A token which is not known to expire.
+    pub fn new(token: impl Into<String>) -> Self {
+        Credential {
+            token: token.into(),
+            ..Credential::default()
+        }
+    }
+
+    /// Whether the scopes of the token are known and do not include
+    /// `scope`. A token of unknown scopes may do anything.
+    pub fn lacks_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            Some(scopes) => !scopes.iter().any(|known| known == scope),
+            None => false,
+        }
+    }
+
+    // the credential in the table of a registry of the credential file
+    fn from_table(table: &Map<String, Value>) -> Option<Self> {
+        Some(Credential {
+            token: table.get("token")?.as_str()?.to_string(),
+            expires_at: table
+                .get("expires_at")
+                .and_then(Value::as_integer)
+                .and_then(|value| u64::try_from(value).ok()),
+            refresh_token: table
+                .get("refresh_token")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            scopes: table.get("scopes").and_then(Value::as_array).map(|scopes| {
+                scopes
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            }),
+        })
+    }
+
+    // replace the credential keys of `table`, keeping the other ones
+    fn write_to_table(&self, table: &mut Map<String, Value>) {
+        for key in CREDENTIAL_KEYS {
+            table.remove(key);
+        }
+        table.insert(String::from("token"), Value::String(self.token.clone()));
+        if let Some(expires_at) = self.expires_at {
+            table.insert(
+                String::from("expires_at"),
+                Value::Integer(expires_at as i64),
+            );
+        }
+        if let Some(refresh_token) = &self.refresh_token {
+            table.insert(
+                String::from("refresh_token"),
+                Value::String(refresh_token.clone()),
+            );
+        }
+        if let Some(scopes) = &self.scopes {
+            let scopes = scopes.iter().cloned().map(Value::String).collect();
+            table.insert(String::from("scopes"), Value::Array(scopes));
+        }
+    }
+}
+
+/// Where the API tokens of the registries are kept, by registry name.
+pub trait CredentialStore: Send + Sync {
+    /// The credential of `registry`, if one is stored.
+    fn get(&self, registry: &str) -> Result<Option<Credential>>;
+    /// Store `credential` as the credential of `registry`, replacing the
+    /// previous one.
+    fn store(&self, registry: &str, credential: &Credential) -> Result<()>;
+    /// Remove the credential of `registry`, returning whether there was one.
+    fn erase(&self, registry: &str) -> Result<bool>;
+    /// Names of the registries with a stored credential.
+    fn list(&self) -> Result<Vec<String>>;
+    /// Where the tokens are kept, for messages.
+    fn describe(&self) -> String;
+}
+
+/// Open the store selected by the `[credential]` table of the credential
+/// file, the plain file store by default:
+///
+/// ```toml
+/// [credential]
+/// store = "encrypted"   # or "file", or "helper"
+/// helper = "my-secret-manager --profile movey"
+/// ```
+///
+/// Setting `helper` alone selects the helper store. `passphrase` is only
+/// called for the encrypted store.
+pub fn open_credential_store(
+    move_home: &str,
+    passphrase: &dyn Fn() -> Result<String>,
+) -> Result<Box<dyn CredentialStore>> {
+    let credential_path = format!("{}{}", move_home, MOVEY_CREDENTIAL_PATH);
+    let toml = read_credential_settings(&credential_path)?;
+    let (store, helper) = store_settings(&toml);
+    match store {
+        "file" => Ok(Box::new(FileStore::new(credential_path))),
+        "encrypted" => Ok(Box::new(EncryptedFileStore::new(
+            encrypted_store_path(move_home),
+            passphrase()?,
+        ))),
+        "helper" => match helper {
+            Some(command) => Ok(Box::new(HelperStore::new(command))),
+            None => bail!("credential.store is `helper` but credential.helper is not set"),
+        },
+        other => bail!(
+            "Unknown credential store `{}`, expected file, encrypted or helper",
+            other
+        ),
+    }
+}
+
+/// Whether the credential file of `move_home` selects the encrypted store,
+/// which cannot be opened without its passphrase.
+pub fn is_encrypted_store(move_home: &str) -> Result<bool> {
+    let toml = read_credential_settings(&format!("{}{}", move_home, MOVEY_CREDENTIAL_PATH))?;
+    Ok(store_settings(&toml).0 == "encrypted")
+}
+
+/// File of the encrypted store of `move_home`.
+pub fn encrypted_store_path(move_home: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", move_home, ENCRYPTED_CREDENTIAL_PATH))
+}
+
+// the credential file, which may not exist
+fn read_credential_settings(credential_path: &str) -> Result<Value> {
+    if Path::new(credential_path).exists() {
+        read_credential_file(credential_path)
+    } else {
+        Ok(Value::Table(Map::new()))
+    }
+}
+
+// the selected store and the helper command of the `[credential]` table
+fn store_settings(toml: &Value) -> (&str, Option<&str>) {
+    let setting = |key: &str| toml.get(CREDENTIAL_TABLE)?.get(key)?.as_str();
+    let helper = setting("helper");
+    let store = setting("store").or_else(|| helper.map(|_| "helper"));
+    (store.unwrap_or("file"), helper)
+}
+
+/// The passphrase in `MOVEY_CREDENTIAL_PASSPHRASE`.
+pub fn env_passphrase() -> Result<String> {
+    match env::var(MOVEY_CREDENTIAL_PASSPHRASE_ENV) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(passphrase),
+        _ => bail!(
+            "The credential store is encrypted, set {} to its passphrase",
+            MOVEY_CREDENTIAL_PASSPHRASE_ENV
+        ),
+    }
+}
+
+/// Write `contents` to `path`, which is only readable by its owner, even if
+/// it already existed.
+///
+/// The contents are written to a temporary file which replaces `path` once
+/// synced, so that `path` is never left half written.
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = sibling_path(path, &format!(".tmp{}", process::id()));
+    let result = write_synced(&temp_path, contents)
+        .and_then(|()| fs::rename(&temp_path, path))
+        .with_context(|| format!("could not write {}", path.display()));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    // make the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Run `update` holding an exclusive advisory lock for `path`, so that
+/// concurrent read-modify-writes of the file do not lose each other's
+/// changes. The lock is taken on `<path>.lock`, since `path` itself is
+/// replaced by every write.
+pub fn with_file_lock<T>(path: &Path, update: impl FnOnce() -> Result<T>) -> Result<T> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let lock_path = sibling_path(path, ".lock");
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("could not open {}", lock_path.display()))?;
+    lock.lock()
+        .with_context(|| format!("could not lock {}", lock_path.display()))?;
+    // the lock is released when `lock` is closed
+    update()
+}
+
+// `path` with `suffix` appended to its file name
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // the mode is only used when the file is created
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Tokens in plain text in the credential file, in the table of each
+/// registry, with their `expires_at`.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStore { path: path.into() }
+    }
+
+    fn read(&self) -> Result<Value> {
+        if !self.path.exists() {
+            return Ok(Value::Table(Map::new()));
+        }
+        read_credential_file(&self.path.to_string_lossy())
+    }
+
+    fn write(&self, toml: &Value) -> Result<()> {
+        write_private_file(&self.path, toml.to_string().as_bytes())
+    }
+}
+
+impl CredentialStore for FileStore {
+    fn get(&self, registry: &str) -> Result<Option<Credential>> {
+        let toml = self.read()?;
+        Ok(registry_table(&toml, registry).and_then(Credential::from_table))
+    }
+
+    fn store(&self, registry: &str, credential: &Credential) -> Result<()> {
+        with_file_lock(&self.path, || {
+            let mut toml = self.read()?;
+            // only update the credential keys, keep the rest of the file intact
+            credential.write_to_table(registry_table_mut(&mut toml, registry)?);
+            self.write(&toml)
+        })
+    }
+
+    fn erase(&self, registry: &str) -> Result<bool> {
+        with_file_lock(&self.path, || {
+            let mut toml = self.read()?;
+            if registry_table(&toml, registry).is_none() {
+                return Ok(false);
+            }
+            let table = registry_table_mut(&mut toml, registry)?;
+            let erased = table.contains_key("token");
+            for key in CREDENTIAL_KEYS {
+                table.remove(key);
+            }
+            if erased {
+                self.write(&toml)?;
+            }
+            Ok(erased)
+        })
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let toml = self.read()?;
+        Ok(registry_names(&toml)
+            .into_iter()
+            .filter(|name| {
+                registry_table(&toml, name).is_some_and(|table| table.contains_key("token"))
+            })
+            .collect())
+    }
+
+    fn describe(&self) -> String {
+        format!("credential file {}", self.path.display())
+    }
+}
+
+/// Tokens in a file encrypted with XChaCha20-Poly1305, with a key derived
+/// from a passphrase by Argon2id.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    passphrase: String,
+}
+
+// content of the encrypted file, with the binary values in base64
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: impl Into<PathBuf>, passphrase: impl Into<String>) -> Self {
+        EncryptedFileStore {
+            path: path.into(),
+            passphrase: passphrase.into(),
+        }
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, Credential>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("could not read {}", self.path.display()))?;
+        let file: EncryptedFile = toml_edit::easy::from_str(&content)
+            .with_context(|| format!("could not parse {}", self.path.display()))?;
+        if file.version != ENCRYPTED_FILE_VERSION {
+            bail!(
+                "{} has version {}, only version {} is supported",
+                self.path.display(),
+                file.version,
+                ENCRYPTED_FILE_VERSION
+            )
+        }
+        let salt = BASE64.decode(&file.salt)?;
+        let nonce = BASE64.decode(&file.nonce)?;
+        let ciphertext = BASE64.decode(&file.ciphertext)?;
+        if nonce.len() != 24 {
+            bail!("invalid nonce in {}", self.path.display())
+        }
+        let plaintext = self
+            .cipher(&salt)?
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| {
+                anyhow!(
+                    "could not decrypt {}, the passphrase may be wrong",
+                    self.path.display()
+                )
+            })?;
+        Ok(toml_edit::easy::from_str(std::str::from_utf8(&plaintext)?)?)
+    }
+
+    fn write(&self, tokens: &BTreeMap<String, Credential>) -> Result<()> {
+        // a new salt and nonce every time, they must never be reused
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher(&salt)?
+            .encrypt(&nonce, toml_edit::easy::to_string(tokens)?.as_bytes())
+            .map_err(|_| anyhow!("could not encrypt the tokens"))?;
+        let file = EncryptedFile {
+            version: ENCRYPTED_FILE_VERSION,
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        write_private_file(&self.path, toml_edit::easy::to_string(&file)?.as_bytes())
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|error| anyhow!("could not derive the encryption key: {}", error))?;
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn get(&self, registry: &str) -> Result<Option<Credential>> {
+        Ok(self.read()?.remove(registry))
+    }
+
+    fn store(&self, registry: &str, credential: &Credential) -> Result<()> {
+        with_file_lock(&self.path, || {
+            let mut tokens = self.read()?;
+            tokens.insert(registry.to_string(), credential.clone());
+            self.write(&tokens)
+        })
+    }
+
+    fn erase(&self, registry: &str) -> Result<bool> {
+        with_file_lock(&self.path, || {
+            let mut tokens = self.read()?;
+            let erased = tokens.remove(registry).is_some();
+            if erased {
+                self.write(&tokens)?;
+            }
+            Ok(erased)
+        })
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.read()?.into_keys().collect())
+    }
+
+    fn describe(&self) -> String {
+        format!("encrypted file {}", self.path.display())
+    }
+}
+
+/// Tokens kept by an external program, like git credential helpers.
+///
+/// The command is run by `sh`, as git runs its helpers, with `get`, `store`
+/// or `erase` as last argument, and reads a JSON object on one line of stdin, so that any value can be
+/// passed:
+///
+/// ```json
+/// {"registry":"<name>","credential":{"token":"<token>","expires_at":1700000000,"refresh_token":"<token>","scopes":["publish"]}}
+/// ```
+///
+/// Only `store` is given the credential, whose fields after `token` are
+/// left out when they are unknown. For `get`, the program prints the
+/// credential object on stdout, or nothing when it has no token. It is also
+/// run with `list` and `{}` on stdin, and prints the JSON array of the
+/// registries it has a token for.
+pub struct HelperStore {
+    command: String,
+}
+
+// what the helper reads on stdin
+#[derive(Serialize)]
+struct HelperRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    credential: Option<&'a Credential>,
+}
+
+impl HelperStore {
+    pub fn new(command: impl Into<String>) -> Self {
+        HelperStore {
+            command: command.into(),
+        }
+    }
+
+    // run the helper with `action` and `request`, returning its stdout
+    fn run(&self, action: &str, request: &HelperRequest) -> Result<String> {
+        let mut request = serde_json::to_string(request)?;
+        request.push('\n');
+
+        if self.command.trim().is_empty() {
+            bail!("credential.helper is empty")
+        }
+        // the shell splits and expands the command, which may quote a path
+        // with spaces, and the action is passed on as its last argument
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg(&self.command)
+            .arg(action)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("could not run credential helper `{}`", self.command))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(request.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!(
+                "credential helper `{} {}` failed with {}: {}",
+                self.command,
+                action,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+impl CredentialStore for HelperStore {
+    fn get(&self, registry: &str) -> Result<Option<Credential>> {
+        let output = self.run(
+            "get",
+            &HelperRequest {
+                registry: Some(registry),
+                credential: None,
+            },
+        )?;
+        if output.trim().is_empty() {
+            return Ok(None);
+        }
+        let credential: Option<Credential> = serde_json::from_str(&output).with_context(|| {
+            format!(
+                "credential helper printed an invalid credential for `{}`",
+                registry
+            )
+        })?;
+        Ok(credential.filter(|credential| !credential.token.is_empty()))
+    }
+
+    fn store(&self, registry: &str, credential: &Credential) -> Result<()> {
+        self.run(
+            "store",
+            &HelperRequest {
+                registry: Some(registry),
+                credential: Some(credential),
+            },
+        )?;
+        Ok(())
+    }
+
+    fn erase(&self, registry: &str) -> Result<bool> {
+        // helpers do not tell whether they had a token
+        if self.get(registry)?.is_none() {
+            return Ok(false);
+        }
+        self.run(
+            "erase",
+            &HelperRequest {
+                registry: Some(registry),
+                credential: None,
+            },
+        )?;
+        Ok(true)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let output = self.run(
+            "list",
+            &HelperRequest {
+                registry: None,
+                credential: None,
+            },
+        )?;
+        if output.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        serde_json::from_str(&output).context("credential helper printed an invalid registry list")
+    }
+
+    fn describe(&self) -> String {
+        format!("credential helper `{}`", self.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREDENTIAL: &str = r#"
+        [registry]
+        token = "movey-token"
+        url = "https://www.movey.net"
+
+        [registries.internal]
+        token = "internal-token"
+        url = "https://movey.example.com"
+
+        [registries.mirror]
+        url = "https://mirror.example.com"
+    "#;
+
+    fn token(store: &dyn CredentialStore, registry: &str) -> Option<String> {
+        store
+            .get(registry)
+            .unwrap()
+            .map(|credential| credential.token)
+    }
+
+    fn setup_dir(test_path: &str) -> PathBuf {
+        let dir = env::current_dir().unwrap().join(test_path);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lacks_scope_only_when_the_scopes_are_known() {
+        let mut credential = Credential::new("token");
+        assert!(!credential.lacks_scope("publish"));
+        credential.scopes = Some(vec![String::from("read")]);
+        assert!(credential.lacks_scope("publish"));
+        assert!(!credential.lacks_scope("read"));
+        credential.scopes = Some(vec![]);
+        assert!(credential.lacks_scope("read"));
+    }
+
+    #[test]
+    fn file_store_erase_keeps_the_other_keys() {
+        let dir = setup_dir("file_store_erase_keeps_the_other_keys");
+        let path = dir.join("movey_credential.toml");
+        fs::write(&path, CREDENTIAL).unwrap();
+        let store = FileStore::new(&path);
+        assert_eq!(store.list().unwrap(), ["movey", "internal"]);
+
+        assert!(store.erase("internal").unwrap());
+        let toml = read_credential_file(&path.to_string_lossy()).unwrap();
+        let internal = registry_table(&toml, "internal").unwrap();
+        assert!(!internal.contains_key("token"));
+        assert_eq!(internal["url"].as_str(), Some("https://movey.example.com"));
+        assert_eq!(token(&store, "movey").as_deref(), Some("movey-token"));
+
+        let credential = Credential {
+            token: String::from("expiring-token"),
+            expires_at: Some(1_700_000_000),
+            refresh_token: Some(String::from("refresh-token")),
+            scopes: Some(vec![String::from("publish"), String::from("read")]),
+        };
+        store.store("mirror", &credential).unwrap();
+        assert_eq!(store.get("mirror").unwrap(), Some(credential));
+        store
+            .store("mirror", &Credential::new("mirror-token"))
+            .unwrap();
+        let toml = read_credential_file(&path.to_string_lossy()).unwrap();
+        let mirror = registry_table(&toml, "mirror").unwrap();
+        assert_eq!(
+            mirror.keys().collect::<Vec<_>>(),
+            ["url", "token"],
+            "the keys of the previous credential are removed"
+        );
+
+        assert!(store.erase("movey").unwrap());
+        assert_eq!(store.get("movey").unwrap(), None);
+        let toml = read_credential_file(&path.to_string_lossy()).unwrap();
+        let movey = registry_table(&toml, "movey").unwrap();
+        assert_eq!(movey["url"].as_str(), Some("https://www.movey.net"));
+
+        assert!(!store.erase("movey").unwrap());
+        assert!(store.erase("mirror").unwrap());
+        assert!(!store.erase("mirror").unwrap());
+        assert!(!store.erase("unknown").unwrap());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_store_makes_existing_file_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = setup_dir("file_store_makes_existing_file_private");
+        let path = dir.join("movey_credential.toml");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        FileStore::new(&path)
+            .store("movey", &Credential::new("token"))
+            .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn file_store_keeps_every_concurrent_store() {
+        let dir = setup_dir("file_store_keeps_every_concurrent_store");
+        let path = dir.join("movey_credential.toml");
+        fs::write(&path, CREDENTIAL).unwrap();
+
+        let threads: Vec<_> = (0..8)
+            .map(|index| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let registry = format!("registry{}", index);
+                    FileStore::new(path).store(&registry, &Credential::new(index.to_string()))
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+
+        let store = FileStore::new(&path);
+        for index in 0..8 {
+            let token = token(&store, &format!("registry{}", index));
+            assert_eq!(token, Some(index.to_string()));
+        }
+        assert_eq!(token(&store, "movey").as_deref(), Some("movey-token"));
+        // only the file and its lock are left, no temporary file
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            ["movey_credential.toml", "movey_credential.toml.lock"]
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn write_private_file_fails_without_touching_the_file() {
+        let dir = setup_dir("write_private_file_fails_without_touching_the_file");
+        // a directory cannot be replaced by the temporary file
+        let path = dir.join("movey_credential.toml");
+        fs::create_dir_all(path.join("content")).unwrap();
+
+        let error = write_private_file(&path, b"token").unwrap_err();
+        assert!(error.to_string().contains("could not write"), "{}", error);
+        assert!(path.join("content").is_dir());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn encrypted_store_works_only_with_its_passphrase() {
+        let dir = setup_dir("encrypted_store_works_only_with_its_passphrase");
+        let path = dir.join("movey_credential.enc");
+        let store = EncryptedFileStore::new(&path, "correct horse");
+
+        assert_eq!(store.get("movey").unwrap(), None);
+        store
+            .store("movey", &Credential::new("movey-token"))
+            .unwrap();
+        let internal = Credential {
+            token: String::from("internal-token"),
+            expires_at: Some(1_700_000_000),
+            refresh_token: Some(String::from("refresh-token")),
+            scopes: Some(vec![]),
+        };
+        store.store("internal", &internal).unwrap();
+        assert_eq!(store.list().unwrap(), ["internal", "movey"]);
+        assert!(!fs::read_to_string(&path).unwrap().contains("movey-token"));
+        assert_eq!(token(&store, "movey").as_deref(), Some("movey-token"));
+
+        assert!(store.erase("movey").unwrap());
+        assert!(!store.erase("movey").unwrap());
+        assert_eq!(store.get("internal").unwrap(), Some(internal));
+
+        let error = EncryptedFileStore::new(&path, "wrong")
+            .get("internal")
+            .unwrap_err();
+        assert!(error.to_string().contains("passphrase may be wrong"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn helper_store_talks_to_the_helper_program() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = setup_dir("helper_store_talks_to_the_helper_program");
+        // keeps the requests it receives in files named by the action
+        let helper = dir.join("helper.sh");
+        let script = format!(
+            "#!/bin/sh\ncat > {dir}/$1\nif [ \"$1\" = get ]; then echo '{{\"token\": \
+            \"helper token\", \"expires_at\": 1700000000, \"scopes\": []}}'; fi\n\
+            if [ \"$1\" = list ]; then echo '[\"internal\"]'; fi\n",
+            dir = dir.display()
+        );
+        fs::write(&helper, script).unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        let store = HelperStore::new(helper.to_string_lossy());
+
+        assert_eq!(
+            store.get("internal").unwrap(),
+            Some(Credential {
+                token: String::from("helper token"),
+                expires_at: Some(1_700_000_000),
+                refresh_token: None,
+                scopes: Some(vec![]),
+            })
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("get")).unwrap(),
+            "{\"registry\":\"internal\"}\n"
+        );
+        store
+            .store(
+                "internal",
+                &Credential {
+                    token: String::from("new-token"),
+                    expires_at: Some(1_800_000_000),
+                    refresh_token: Some(String::from("refresh-token")),
+                    scopes: Some(vec![String::from("publish"), String::from("two words")]),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("store")).unwrap(),
+            "{\"registry\":\"internal\",\"credential\":{\"token\":\"new-token\",\
+            \"expires_at\":1800000000,\"refresh_token\":\"refresh-token\",\
+            \"scopes\":[\"publish\",\"two words\"]}}\n"
+        );
+        assert_eq!(store.list().unwrap(), ["internal"]);
+        assert_eq!(fs::read_to_string(dir.join("list")).unwrap(), "{}\n");
+        assert!(store.erase("internal").unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.join("erase")).unwrap(),
+            "{\"registry\":\"internal\"}\n"
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn helper_store_runs_the_helper_through_the_shell() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = setup_dir("helper store runs the helper through the shell");
+        let helper = dir.join("my helper.sh");
+        let script = format!(
+            "#!/bin/sh\ncat > /dev/null\necho \"$@\" > '{}/args'\n",
+            dir.display()
+        );
+        fs::write(&helper, script).unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        let store = HelperStore::new(format!("'{}' --profile movey", helper.display()));
+
+        assert_eq!(store.get("movey").unwrap(), None);
+        assert_eq!(
+            fs::read_to_string(dir.join("args")).unwrap(),
+            "--profile movey get\n"
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn open_credential_store_follows_the_credential_table() {
+        let dir = setup_dir("open_credential_store_follows_the_credential_table");
+        let move_home = dir.to_string_lossy().to_string();
+        let credential_path = format!("{}{}", move_home, MOVEY_CREDENTIAL_PATH);
+        let no_passphrase = || -> Result<String> { bail!("no passphrase") };
+
+        let store = open_credential_store(&move_home, &no_passphrase).unwrap();
+        assert!(store.describe().starts_with("credential file"));
+
+        fs::write(&credential_path, "[credential]\nstore = \"encrypted\"\n").unwrap();
+        assert!(open_credential_store(&move_home, &no_passphrase).is_err());
+        let store = open_credential_store(&move_home, &|| Ok(String::from("secret"))).unwrap();
+        assert!(store.describe().starts_with("encrypted file"));
+        assert!(is_encrypted_store(&move_home).unwrap());
+
+        fs::write(&credential_path, "[credential]\nhelper = \"pass-movey\"\n").unwrap();
+        let store = open_credential_store(&move_home, &no_passphrase).unwrap();
+        assert_eq!(store.describe(), "credential helper `pass-movey`");
+        assert!(!is_encrypted_store(&move_home).unwrap());
+
+        fs::write(&credential_path, "[credential]\nstore = \"vault\"\n").unwrap();
+        assert!(open_credential_store(&move_home, &no_passphrase).is_err());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
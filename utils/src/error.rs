@@ -1,113 +1,78 @@
-use std::fmt;
+use std::backtrace::BacktraceStatus;
+use std::error::Error as StdError;
+use std::fmt::Write;
 use std::result;
 
-use failure::{Backtrace, Context, Fail};
+use thiserror::Error;
 
 /// A type alias that forces the usage of the custom error type.
 pub type Result<T> = result::Result<T, Error>;
 
 /// Custom error type for handling errors.
-#[derive(Debug)]
-pub struct Error {
-    inner: Context<ErrorKind>,
-}
-
-impl Error {
-    pub fn kind(&self) -> ErrorKind {
-        *self.inner.get_context()
-    }
-}
-
-impl Fail for Error {
-    fn cause(&self) -> Option<&dyn Fail> {
-        self.inner.cause()
-    }
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.inner.backtrace()
-    }
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.kind())
-    }
-}
-
-#[derive(Debug, Copy, Clone, PartialEq, Fail)]
-pub enum ErrorKind {
-    ConfigError,
-    PoisonError,
-    IoError,
-    ClapError,
-    LoggerError,
-    AnyhowError,
-}
-
-impl fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "Undefined Error")
-    }
-}
-
-impl From<ErrorKind> for Error {
-    fn from(kind: ErrorKind) -> Error {
-        Error {
-            inner: Context::new(kind),
-        }
-    }
-}
-
-impl From<Context<ErrorKind>> for Error {
-    fn from(inner: failure::Context<ErrorKind>) -> Error {
-        Error { inner }
-    }
-}
-
-impl From<config::ConfigError> for Error {
-    fn from(err: config::ConfigError) -> Self {
-        Error {
-            inner: err.context(ErrorKind::ConfigError),
-        }
-    }
+///
+/// Every variant keeps the error it comes from as its source, so the whole
+/// chain can be reported with [`Error::report`].
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid configuration")]
+    Config(#[from] config::ConfigError),
+    #[error("the configuration lock is poisoned")]
+    Poison,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Clap(#[from] clap::Error),
+    #[error("could not set up logging")]
+    Logger(#[from] log::SetLoggerError),
+    /// Error of a command, with its own context and causes.
+    #[error(transparent)]
+    Command(#[from] anyhow::Error),
 }
 
 impl<T> From<std::sync::PoisonError<T>> for Error {
     fn from(_err: std::sync::PoisonError<T>) -> Self {
-        Error {
-            inner: Context::from(ErrorKind::PoisonError),
-        }
+        Error::Poison
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Error {
-            inner: err.context(ErrorKind::IoError),
-        }
-    }
-}
-
-impl From<clap::Error> for Error {
-    fn from(err: clap::Error) -> Self {
-        Error {
-            inner: err.context(ErrorKind::ClapError),
+impl Error {
+    /// The message of the error followed by its causes, and the backtrace
+    /// of command errors when `verbose` is set and it was captured.
+    pub fn report(&self, verbose: bool) -> String {
+        let mut causes: Vec<String> = match self {
+            Error::Command(error) => error.chain().map(ToString::to_string).collect(),
+            _ => {
+                let mut causes = vec![self.to_string()];
+                let mut source = self.source();
+                while let Some(error) = source {
+                    causes.push(error.to_string());
+                    source = error.source();
+                }
+                causes
+            }
+        };
+        // transparent variants repeat the message of their source
+        causes.dedup();
+
+        let mut report = causes.remove(0);
+        if !causes.is_empty() {
+            report.push_str("\n\nCaused by:");
+            // like anyhow, causes are numbered when there are several of them
+            for (index, cause) in causes.iter().enumerate() {
+                let number = if causes.len() > 1 {
+                    format!("{}: ", index)
+                } else {
+                    String::new()
+                };
+                let indent = format!("\n    {}", " ".repeat(number.len()));
+                let _ = write!(report, "\n    {}{}", number, cause.replace('\n', &indent));
+            }
         }
-    }
-}
-
-impl From<log::SetLoggerError> for Error {
-    fn from(err: log::SetLoggerError) -> Self {
-        Error {
-            inner: err.context(ErrorKind::LoggerError),
+        if let Error::Command(error) = self {
+            if verbose && error.backtrace().status() == BacktraceStatus::Captured {
+                let _ = write!(report, "\n\nStack backtrace:\n{}", error.backtrace());
+            }
         }
+        report
     }
 }
-
-impl From<anyhow::Error> for Error {
-    fn from(err: anyhow::Error) -> Self {
-        eprintln!("{err:?}");
-        Error {
-            inner: Context::from(ErrorKind::AnyhowError),
-        }
-    }   
-}
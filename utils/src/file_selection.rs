@@ -0,0 +1,196 @@
+// Copyright (c) The Move Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+
+use crate::move_manifest::{PackageInfo, MOVE_MANIFEST_FILE};
+
+pub const MOVEY_IGNORE_FILE: &str = ".moveyignore";
+
+/// Decides which files of a package are published.
+///
+/// When the `include` array of `[package]` is set, only files matching one
+/// of its patterns are kept. Files matching `exclude` or a pattern of the
+/// `.moveyignore` file at the package root are then removed. All patterns
+/// use the gitignore syntax. `Move.toml` is always kept.
+pub struct FileSelector {
+    include: Option<Gitignore>,
+    exclude: Gitignore,
+}
+
+impl FileSelector {
+    pub fn new(package_root: &Path, package: &PackageInfo) -> Result<FileSelector> {
+        let include = if package.include.is_empty() {
+            None
+        } else {
+            Some(build_matcher(package_root, &package.include, "include")?)
+        };
+
+        let mut builder = GitignoreBuilder::new(package_root);
+        let ignore_file = package_root.join(MOVEY_IGNORE_FILE);
+        if ignore_file.is_file() {
+            if let Some(error) = builder.add(&ignore_file) {
+                return Err(error).with_context(|| format!("invalid {}", MOVEY_IGNORE_FILE));
+            }
+        }
+        for pattern in &package.exclude {
+            builder
+                .add_line(None, pattern)
+                .with_context(|| format!("invalid `exclude` pattern `{}`", pattern))?;
+        }
+        let exclude = builder
+            .build()
+            .with_context(|| format!("invalid {}", MOVEY_IGNORE_FILE))?;
+
+        Ok(FileSelector { include, exclude })
+    }
+
+    /// Whether `path`, relative to the package root, is published.
+    pub fn is_selected(&self, path: &Path) -> bool {
+        if path == Path::new(MOVE_MANIFEST_FILE) {
+            return true;
+        }
+        if let Some(include) = &self.include {
+            if !include.matched_path_or_any_parents(path, false).is_ignore() {
+                return false;
+            }
+        }
+        !self
+            .exclude
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+    }
+
+    /// The selected files among `files`, in their original order.
+    pub fn select(&self, files: &[PathBuf]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .filter(|file| self.is_selected(file))
+            .cloned()
+            .collect()
+    }
+}
+
+fn build_matcher(package_root: &Path, patterns: &[String], field: &str) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(package_root);
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("invalid `{}` pattern `{}`", field, pattern))?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn package(include: &[&str], exclude: &[&str]) -> PackageInfo {
+        PackageInfo {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn files() -> Vec<PathBuf> {
+        [
+            "Move.toml",
+            "README.md",
+            "sources/A.move",
+            "sources/nested/B.move",
+            "tests/ATests.move",
+            "build/Package/bytecode_modules/A.mv",
+            "docs/A.md",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect()
+    }
+
+    #[test]
+    fn select_keeps_everything_without_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let selector = FileSelector::new(dir.path(), &package(&[], &[])).unwrap();
+        assert_eq!(selector.select(&files()), files());
+    }
+
+    #[test]
+    fn select_works_with_moveyignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(MOVEY_IGNORE_FILE),
+            "# build output\nbuild/\n*.md\n!README.md\n",
+        )
+        .unwrap();
+        let selector = FileSelector::new(dir.path(), &package(&[], &[])).unwrap();
+        assert_eq!(
+            selector.select(&files()),
+            vec![
+                PathBuf::from("Move.toml"),
+                PathBuf::from("README.md"),
+                PathBuf::from("sources/A.move"),
+                PathBuf::from("sources/nested/B.move"),
+                PathBuf::from("tests/ATests.move"),
+            ]
+        );
+    }
+
+    #[test]
+    fn select_works_with_include_and_exclude() {
+        let dir = tempfile::tempdir().unwrap();
+        let selector =
+            FileSelector::new(dir.path(), &package(&["sources/", "*.md"], &["docs/"])).unwrap();
+        assert_eq!(
+            selector.select(&files()),
+            vec![
+                PathBuf::from("Move.toml"),
+                PathBuf::from("README.md"),
+                PathBuf::from("sources/A.move"),
+                PathBuf::from("sources/nested/B.move"),
+            ]
+        );
+    }
+
+    #[test]
+    fn select_combines_exclude_and_moveyignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(MOVEY_IGNORE_FILE), "tests/\n").unwrap();
+        let selector =
+            FileSelector::new(dir.path(), &package(&[], &["/build", "**/nested"])).unwrap();
+        assert_eq!(
+            selector.select(&files()),
+            vec![
+                PathBuf::from("Move.toml"),
+                PathBuf::from("README.md"),
+                PathBuf::from("sources/A.move"),
+                PathBuf::from("docs/A.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn select_always_keeps_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let selector = FileSelector::new(dir.path(), &package(&["sources/"], &["*.toml"])).unwrap();
+        assert!(selector.is_selected(Path::new("Move.toml")));
+    }
+
+    #[test]
+    fn new_fails_with_invalid_pattern() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = FileSelector::new(dir.path(), &package(&[], &["sources/{a"]))
+            .err()
+            .unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("invalid `exclude` pattern `sources/{a`"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright (c) The Move Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Context, Result};
+use git2::{Repository, StatusOptions};
+use std::path::{Path, PathBuf};
+
+/// A git remote and the url it fetches from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRemote {
+    pub name: String,
+    pub url: String,
+}
+
+/// Read-only view of the git repository containing a package directory.
+///
+/// Everything is read in-process, so the `git` executable is not required
+/// and paths are not limited to UTF-8.
+pub struct GitRepository {
+    repo: Repository,
+    // path of the package directory relative to the repository root
+    prefix: PathBuf,
+}
+
+impl GitRepository {
+    /// Open the repository that contains `path`, searching parent directories.
+    pub fn discover(path: &Path) -> Result<GitRepository> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("invalid directory {}", path.display()))?;
+        let repo = match Repository::discover(&path) {
+            Ok(repo) => repo,
+            Err(_) => bail!("invalid git repository"),
+        };
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir.canonicalize()?,
+            None => bail!("invalid git repository: bare repositories are not supported"),
+        };
+        let prefix = path
+            .strip_prefix(&workdir)
+            .context("invalid git repository")?
+            .to_path_buf();
+        Ok(GitRepository { repo, prefix })
+    }
+
+    /// Root of the working tree.
+    pub fn workdir(&self) -> &Path {
+        // `discover` rejects bare repositories
+        self.repo.workdir().unwrap()
+    }
+
+    /// Path of the package directory relative to the working tree root,
+    /// empty when the package is at the top level.
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Look at the directory `prefix` of the working tree from now on, like
+    /// a repository discovered from it.
+    pub fn set_prefix(&mut self, prefix: impl Into<PathBuf>) {
+        self.prefix = prefix.into();
+    }
+
+    /// All remotes with a UTF-8 name and url, in the order git reports them.
+    pub fn remotes(&self) -> Result<Vec<GitRemote>> {
+        let mut remotes = vec![];
+        for name in self.repo.remotes()?.iter() {
+            let name = match name? {
+                Some(name) => name,
+                None => continue,
+            };
+            let remote = self.repo.find_remote(name)?;
+            if let Ok(url) = remote.url() {
+                remotes.push(GitRemote {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                });
+            }
+        }
+        Ok(remotes)
+    }
+
+    /// Files tracked in the index under the package directory, relative to it
+    /// and sorted, like `git ls-files` run from the package directory.
+    pub fn tracked_files(&self) -> Result<Vec<PathBuf>> {
+        let index = self.repo.index()?;
+        let mut files = vec![];
+        for entry in index.iter() {
+            let path = bytes_to_path(&entry.path)?;
+            if let Ok(relative) = path.strip_prefix(&self.prefix) {
+                files.push(relative.to_path_buf());
+            }
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// Hash of the commit HEAD points at, or `None` before the first commit.
+    pub fn head_commit(&self) -> Result<Option<String>> {
+        match self.repo.head() {
+            Ok(head) => Ok(Some(head.peel_to_commit()?.id().to_string())),
+            Err(error) if error.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Name of the branch HEAD points at, or `None` when HEAD is detached.
+    pub fn head_branch(&self) -> Result<Option<String>> {
+        let head = self.repo.find_reference("HEAD")?;
+        Ok(head
+            .symbolic_target()?
+            .and_then(|target| target.strip_prefix("refs/heads/"))
+            .map(String::from))
+    }
+
+    /// Name of a tag pointing at the HEAD commit, the first one in
+    /// alphabetical order when there are several.
+    pub fn head_tag(&self) -> Result<Option<String>> {
+        let head = match self.head_commit()? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let mut tags = vec![];
+        for name in self.repo.tag_names(None)?.iter() {
+            let name = match name? {
+                Some(name) => name,
+                None => continue,
+            };
+            let reference = self.repo.find_reference(&format!("refs/tags/{}", name))?;
+            // tags may also point at trees or blobs
+            if let Ok(commit) = reference.peel_to_commit() {
+                if commit.id().to_string() == head {
+                    tags.push(name.to_string());
+                }
+            }
+        }
+        tags.sort();
+        Ok(tags.into_iter().next())
+    }
+
+    /// Modified, staged, deleted and untracked files under the package
+    /// directory, relative to it. Ignored files are not reported.
+    pub fn dirty_files(&self) -> Result<Vec<PathBuf>> {
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        let mut files = vec![];
+        for entry in self.repo.statuses(Some(&mut options))?.iter() {
+            let path = bytes_to_path(entry.path_bytes())?;
+            if let Ok(relative) = path.strip_prefix(&self.prefix) {
+                files.push(relative.to_path_buf());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Whether the package directory has uncommitted changes.
+    pub fn is_dirty(&self) -> Result<bool> {
+        Ok(!self.dirty_files()?.is_empty())
+    }
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> Result<PathBuf> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    Ok(PathBuf::from(OsStr::from_bytes(bytes)))
+}
+
+// git stores paths as UTF-8 on platforms without byte paths
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> Result<PathBuf> {
+    match std::str::from_utf8(bytes) {
+        Ok(path) => Ok(PathBuf::from(path)),
+        Err(_) => bail!("non-Unicode file name in git index"),
+    }
+}
@@ -4,4 +4,11 @@ pub mod error;
 pub mod logger;
 pub mod app_config;
 pub mod movey_credential;
-pub mod env;
\ No newline at end of file
+pub mod credential_store;
+pub mod git;
+pub mod git_remote;
+pub mod move_manifest;
+pub mod move_workspace;
+pub mod package_archive;
+pub mod env;
+pub mod file_selection;
\ No newline at end of file
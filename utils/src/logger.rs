@@ -7,7 +7,7 @@ use super::error::Result;
 pub fn setup_logging() -> Result<()> {
     // Setup Logging
     let _guard = slog_scope::set_global_logger(default_root_logger()?);
-    let _log_guard = slog_stdlog::init().unwrap();
+    slog_stdlog::init().unwrap();
 
     Ok(())
 }
@@ -17,8 +17,12 @@ pub fn default_root_logger() -> Result<slog::Logger> {
     let syslog_drain = default_syslog_drain().unwrap_or(default_discard()?);
     let term_drain = default_term_drain().unwrap_or(default_discard()?);
 
-    // Merge drains
+    // Merge drains, leaving out the debug logs of dependencies
     let drain = slog::Duplicate(syslog_drain, term_drain).fuse();
+    let drain = slog::Filter::new(drain, |record: &slog::Record| {
+        record.level().is_at_least(slog::Level::Info) || is_own_target(record.tag())
+    })
+    .fuse();
 
     // Create Logger
     let logger = slog::Logger::root(drain, o!("who" => "rust-starter"));
@@ -27,6 +31,18 @@ pub fn default_root_logger() -> Result<slog::Logger> {
     Ok(logger)
 }
 
+// crates of the workspace, whose debug logs are kept; those of the
+// dependencies, like the walks of `ignore` or the connections of `hyper`,
+// would fill the output
+const OWN_CRATES: [&str; 5] = ["movey", "cli", "core", "utils", "movey_client"];
+
+// `target` is the tag of the record: the target of a `log` record, empty
+// for the records of slog itself
+fn is_own_target(target: &str) -> bool {
+    let krate = target.split("::").next().unwrap_or_default();
+    target.is_empty() || OWN_CRATES.contains(&krate)
+}
+
 fn default_discard() -> Result<slog_async::Async> {
     let drain = slog_async::Async::default(slog::Discard);
 
@@ -0,0 +1,295 @@
+// Copyright (c) The Move Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+use toml_edit::easy::Value;
+
+pub const MOVE_MANIFEST_FILE: &str = "Move.toml";
+
+/// Typed content of a Move package manifest (`Move.toml`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MoveManifest {
+    pub package: PackageInfo,
+    #[serde(default)]
+    pub addresses: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    #[serde(default, rename = "dev-dependencies")]
+    pub dev_dependencies: BTreeMap<String, Dependency>,
+}
+
+/// The `[package]` table of a Move manifest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub name: String,
+    #[serde(deserialize_with = "deserialize_version")]
+    pub version: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub license: Option<String>,
+    /// Patterns of the files to publish, all files when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Patterns of the files to leave out when publishing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Any other field of the `[package]` table, kept as written.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// An entry of `[dependencies]` or `[dev-dependencies]`, either a local path
+/// or a git repository pinned to a revision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawDependency")]
+pub struct Dependency {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub addr_subst: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Any other field of the dependency, like `override`, kept as written.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct RawDependency {
+    local: Option<String>,
+    git: Option<String>,
+    rev: Option<String>,
+    subdir: Option<String>,
+    #[serde(default)]
+    addr_subst: BTreeMap<String, String>,
+    digest: Option<String>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
+}
+
+impl TryFrom<RawDependency> for Dependency {
+    type Error = String;
+
+    fn try_from(raw: RawDependency) -> std::result::Result<Self, Self::Error> {
+        match (&raw.local, &raw.git) {
+            (Some(_), Some(_)) => {
+                return Err(String::from(
+                    "dependency cannot have both `local` and `git` fields",
+                ))
+            }
+            (None, None) => {
+                return Err(String::from(
+                    "dependency must have either a `local` or a `git` field",
+                ))
+            }
+            (None, Some(_)) if raw.rev.is_none() => {
+                return Err(String::from("git dependency is missing the `rev` field"))
+            }
+            _ => {}
+        }
+        Ok(Dependency {
+            local: raw.local,
+            git: raw.git,
+            rev: raw.rev,
+            subdir: raw.subdir,
+            addr_subst: raw.addr_subst,
+            digest: raw.digest,
+            extra: raw.extra,
+        })
+    }
+}
+
+// Move package versions are always `<major>.<minor>.<patch>`
+fn deserialize_version<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let version = String::deserialize(deserializer)?;
+    let parts: Vec<&str> = version.split('.').collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.parse::<u64>().is_err()) {
+        return Err(serde::de::Error::custom(format!(
+            "invalid version `{}`, expected `<major>.<minor>.<patch>`",
+            version
+        )));
+    }
+    Ok(version)
+}
+
+impl MoveManifest {
+    /// Read and parse the manifest at `manifest_path`.
+    pub fn read(manifest_path: &Path) -> Result<MoveManifest> {
+        let content = match fs::read_to_string(manifest_path) {
+            Ok(content) => content,
+            Err(_) => bail!("{} not found", MOVE_MANIFEST_FILE),
+        };
+        if content.trim().is_empty() {
+            bail!("{} not found", MOVE_MANIFEST_FILE)
+        }
+        MoveManifest::parse(&content)
+            .with_context(|| format!("could not parse {}", manifest_path.display()))
+    }
+
+    /// Parse the content of a manifest. Errors report the line and column of
+    /// the offending value.
+    pub fn parse(content: &str) -> Result<MoveManifest> {
+        toml_edit_de::de::from_str(content).map_err(|error| {
+            let message = error.message().trim_end().replace('\n', ", ");
+            match error.span() {
+                Some(span) => {
+                    let (line, column) = line_column(content, span.start);
+                    anyhow!("{} at line {}, column {}", message, line, column)
+                }
+                None => anyhow!(message),
+            }
+        })
+    }
+}
+
+// 1-based line and column of the byte `offset` in `content`.
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        let content = r#"
+            [package]
+            name = "Package1"
+            version = "1.2.3"
+            authors = ["Alice", "Bob"]
+            license = "Apache-2.0"
+            edition = "2024.beta"
+            include = ["sources/"]
+            exclude = ["sources/*.spec.move"]
+
+            [addresses]
+            Std = "0x1"
+            Package1 = "_"
+
+            [dependencies]
+            MoveStdlib = { git = "https://github.com/move-language/move.git", subdir = "language/move-stdlib", rev = "main" }
+            Local = { local = "../local", addr_subst = { "Std" = "0x1" }, override = true }
+
+            [dev-dependencies]
+            Test = { local = "../test" }
+            "#;
+        let manifest = MoveManifest::parse(content).unwrap();
+
+        assert_eq!(manifest.package.name, "Package1");
+        assert_eq!(manifest.package.version, "1.2.3");
+        assert_eq!(manifest.package.authors, vec!["Alice", "Bob"]);
+        assert_eq!(manifest.package.license.as_deref(), Some("Apache-2.0"));
+        assert_eq!(manifest.package.include, vec!["sources/"]);
+        assert_eq!(manifest.package.exclude, vec!["sources/*.spec.move"]);
+        assert_eq!(
+            manifest.package.extra.get("edition"),
+            Some(&Value::String(String::from("2024.beta")))
+        );
+        assert_eq!(manifest.addresses.get("Package1").unwrap(), "_");
+        let stdlib = manifest.dependencies.get("MoveStdlib").unwrap();
+        assert_eq!(stdlib.rev.as_deref(), Some("main"));
+        assert_eq!(stdlib.subdir.as_deref(), Some("language/move-stdlib"));
+        let local = manifest.dependencies.get("Local").unwrap();
+        assert_eq!(local.local.as_deref(), Some("../local"));
+        assert_eq!(local.addr_subst.get("Std").unwrap(), "0x1");
+        assert_eq!(local.extra.get("override"), Some(&Value::Boolean(true)));
+        assert!(manifest.dev_dependencies.contains_key("Test"));
+    }
+
+    #[test]
+    fn parse_works_with_only_package_table() {
+        let content = r#"
+            [package]
+            name = "Package1"
+            version = "0.0.0"
+            "#;
+        let manifest = MoveManifest::parse(content).unwrap();
+
+        assert!(manifest.package.authors.is_empty());
+        assert!(manifest.package.license.is_none());
+        assert!(manifest.addresses.is_empty());
+        assert!(manifest.dependencies.is_empty());
+        assert!(manifest.dev_dependencies.is_empty());
+    }
+
+    #[test]
+    fn parse_fails_with_line_and_column_if_toml_is_malformed() {
+        let content = "[package]\nname = Package1\nversion = \"0.0.0\"\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("line 2, column 8"), "{}", error);
+    }
+
+    #[test]
+    fn parse_fails_with_line_and_column_if_field_has_wrong_type() {
+        let content = "[package]\nname = \"Package1\"\nversion = 1\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("line 3, column 11"), "{}", error);
+    }
+
+    #[test]
+    fn parse_reports_the_position_of_a_value_after_a_multi_line_array() {
+        let content = "[package]\nname = \"Package1\"\nauthors = [\n    \"Alice = Bob\",\n]\n\
+            version = 1\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("line 6, column 11"), "{}", error);
+    }
+
+    #[test]
+    fn parse_reports_the_position_of_a_value_with_a_quoted_key_containing_equals() {
+        let content = "[package]\nname = \"Package1\"\nversion = \"0.0.0\"\n\n\
+            [dependencies]\n\"Std = 1\" = { path = \"../std\" }\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("line 6, column 13"), "{}", error);
+    }
+
+    #[test]
+    fn parse_fails_if_version_is_invalid() {
+        let content = "[package]\nname = \"Package1\"\nversion = \"1.0\"\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("line 3, column 11"), "{}", error);
+        assert!(error.contains("invalid version `1.0`"), "{}", error);
+    }
+
+    #[test]
+    fn parse_fails_if_package_table_is_missing() {
+        let content = "[addresses]\nStd = \"0x1\"\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("missing field `package`"), "{}", error);
+    }
+
+    #[test]
+    fn parse_fails_if_dependency_is_invalid() {
+        let content = "[package]\nname = \"Package1\"\nversion = \"0.0.0\"\n\n\
+            [dependencies]\nStd = { git = \"https://github.com/move-language/move.git\" }\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(error.contains("line 6, column 7"), "{}", error);
+        assert!(error.contains("missing the `rev` field"), "{}", error);
+
+        let content = "[package]\nname = \"Package1\"\nversion = \"0.0.0\"\n\n\
+            [dependencies]\nStd = { path = \"../std\" }\n";
+        let error = MoveManifest::parse(content).unwrap_err().to_string();
+        assert!(
+            error.contains("must have either a `local` or a `git` field"),
+            "{}",
+            error
+        );
+    }
+}
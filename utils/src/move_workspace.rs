@@ -0,0 +1,200 @@
+// Copyright (c) The Move Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+use crate::move_manifest::{MoveManifest, MOVE_MANIFEST_FILE};
+
+/// A Move package found in a workspace.
+#[derive(Debug, Clone)]
+pub struct WorkspacePackage {
+    /// Directory of the package, relative to the workspace root.
+    pub path: PathBuf,
+    pub manifest: MoveManifest,
+}
+
+/// Read the packages whose manifests are among `files`, given relative to
+/// `root`, and sort them so that every package comes after the workspace
+/// packages it depends on through a `local` dependency.
+pub fn workspace_packages(root: &Path, files: &[PathBuf]) -> Result<Vec<WorkspacePackage>> {
+    let mut packages = vec![];
+    for file in files {
+        if file.file_name() != Some(MOVE_MANIFEST_FILE.as_ref()) {
+            continue;
+        }
+        let manifest = MoveManifest::read(&root.join(file))
+            .with_context(|| format!("invalid package in {}", file.display()))?;
+        let path = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        packages.push(WorkspacePackage { path, manifest });
+    }
+    sort_by_dependencies(packages)
+}
+
+/// Sort `packages` so that local dependencies come first, keeping the order
+/// of their paths otherwise.
+pub fn sort_by_dependencies(mut packages: Vec<WorkspacePackage>) -> Result<Vec<WorkspacePackage>> {
+    packages.sort_by(|a, b| a.path.cmp(&b.path));
+    let dependencies: Vec<Vec<usize>> = packages
+        .iter()
+        .map(|package| local_dependencies(package, &packages))
+        .collect();
+
+    let mut sorted: Vec<usize> = vec![];
+    // 0: not visited, 1: being visited, 2: sorted
+    let mut state = vec![0u8; packages.len()];
+    for index in 0..packages.len() {
+        visit(index, &dependencies, &packages, &mut state, &mut sorted)?;
+    }
+
+    let mut packages: Vec<Option<WorkspacePackage>> = packages.into_iter().map(Some).collect();
+    Ok(sorted
+        .into_iter()
+        .map(|index| packages[index].take().unwrap())
+        .collect())
+}
+
+/// Indexes in `packages` of the packages `package` depends on through a
+/// `local` dependency, including dev-dependencies.
+pub fn local_dependencies(package: &WorkspacePackage, packages: &[WorkspacePackage]) -> Vec<usize> {
+    let mut dependencies: Vec<usize> = package
+        .manifest
+        .dependencies
+        .values()
+        .chain(package.manifest.dev_dependencies.values())
+        .filter_map(|dependency| dependency.local.as_ref())
+        .filter_map(|local| {
+            let path = normalize_path(&package.path.join(local));
+            packages.iter().position(|other| other.path == path)
+        })
+        .collect();
+    dependencies.sort_unstable();
+    dependencies.dedup();
+    dependencies
+}
+
+fn visit(
+    index: usize,
+    dependencies: &[Vec<usize>],
+    packages: &[WorkspacePackage],
+    state: &mut [u8],
+    sorted: &mut Vec<usize>,
+) -> Result<()> {
+    match state[index] {
+        2 => return Ok(()),
+        1 => bail!(
+            "dependency cycle detected involving package {}",
+            packages[index].manifest.package.name
+        ),
+        _ => {}
+    }
+    state[index] = 1;
+    for dependency in &dependencies[index] {
+        visit(*dependency, dependencies, packages, state, sorted)?;
+    }
+    state[index] = 2;
+    sorted.push(index);
+    Ok(())
+}
+
+// resolve `.` and `..` without touching the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut components: Vec<Component> = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    components.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_manifest(root: &Path, path: &str, name: &str, dependencies: &str) -> PathBuf {
+        let manifest_path = PathBuf::from(path).join(MOVE_MANIFEST_FILE);
+        fs::create_dir_all(root.join(path)).unwrap();
+        fs::write(
+            root.join(&manifest_path),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.0.0\"\n\n[dependencies]\n{}",
+                name, dependencies
+            ),
+        )
+        .unwrap();
+        manifest_path
+    }
+
+    fn names(packages: &[WorkspacePackage]) -> Vec<&str> {
+        packages
+            .iter()
+            .map(|package| package.manifest.package.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn workspace_packages_sorts_by_local_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            write_manifest(dir.path(), "", "Root", "App = { local = \"apps/app\" }\n"),
+            write_manifest(
+                dir.path(),
+                "apps/app",
+                "App",
+                "Base = { local = \"../../packages/base\" }\n\
+                Std = { git = \"https://github.com/move-language/move.git\", rev = \"main\" }\n",
+            ),
+            write_manifest(dir.path(), "packages/base", "Base", ""),
+            write_manifest(dir.path(), "packages/util", "Util", ""),
+            PathBuf::from("packages/base/sources/Base.move"),
+        ];
+
+        let packages = workspace_packages(dir.path(), &files).unwrap();
+        assert_eq!(names(&packages), vec!["Base", "App", "Root", "Util"]);
+        assert_eq!(packages[0].path, PathBuf::from("packages/base"));
+        assert_eq!(packages[2].path, PathBuf::from(""));
+    }
+
+    #[test]
+    fn workspace_packages_fails_with_dependency_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            write_manifest(dir.path(), "a", "A", "B = { local = \"../b\" }\n"),
+            write_manifest(dir.path(), "b", "B", "A = { local = \"../a/\" }\n"),
+        ];
+
+        let error = workspace_packages(dir.path(), &files).unwrap_err();
+        assert!(error.to_string().contains("dependency cycle"), "{}", error);
+    }
+
+    #[test]
+    fn workspace_packages_fails_with_invalid_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("a/Move.toml"), "[package]\nname = \"A\"\n").unwrap();
+
+        let error = workspace_packages(dir.path(), &[PathBuf::from("a/Move.toml")]).unwrap_err();
+        assert!(error.to_string().contains("invalid package in a/Move.toml"));
+    }
+
+    #[test]
+    fn normalize_path_works() {
+        assert_eq!(
+            normalize_path(Path::new("apps/app/../../packages/./base")),
+            PathBuf::from("packages/base")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../outside")),
+            PathBuf::from("../outside")
+        );
+    }
+}
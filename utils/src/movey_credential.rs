@@ -2,59 +2,361 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{bail, Context, Result};
-use std::fs;
-use toml_edit::easy::Value;
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::easy::{map::Map, Value};
 
-#[cfg(debug_assertions)]
-pub const MOVEY_URL: &str = "https://movey-app-staging.herokuapp.com";
-#[cfg(not(debug_assertions))]
+use crate::credential_store::{open_credential_store, Credential, CredentialStore};
+
+/// Url of Movey, used when no other url is configured, whatever the build.
 pub const MOVEY_URL: &str = "https://www.movey.net";
 pub const MOVEY_CREDENTIAL_PATH: &str = "/movey_credential.toml";
+/// Environment variable holding an API token for Movey, used instead of the
+/// credential file so that CI does not have to write it to disk. The other
+/// registries have their own, see [`registry_token_env`].
+pub const MOVEY_TOKEN_ENV: &str = "MOVEY_TOKEN";
+/// Environment variable overriding the url of the registry.
+pub const MOVEY_REGISTRY_URL_ENV: &str = "MOVEY_REGISTRY_URL";
+/// Project configuration, looked up from the package directory upwards. It
+/// has the `[registry]` and `[registries.<name>]` tables of the credential
+/// file, with urls but without tokens.
+pub const PROJECT_CONFIG_FILE: &str = "Movey.toml";
+
+/// Name of the registry whose credentials are in the `[registry]` table,
+/// the only one before named registries were added.
+pub const DEFAULT_REGISTRY: &str = "movey";
+/// Table of the named registries, like `[registries.staging]`.
+pub const REGISTRIES_TABLE: &str = "registries";
+// key of the `[registry]` table naming the registry used by default
+const DEFAULT_KEY: &str = "default";
+
+/// Credential of a registry with the store it comes from, so that a
+/// refreshed token can be saved back.
+pub struct RegistryCredential {
+    pub name: String,
+    pub credential: Credential,
+    /// `None` when the token comes from an environment variable.
+    pub store: Option<Box<dyn CredentialStore>>,
+}
 
+/// The token in the environment variable of `registry`, else the one in the
+/// credential store. `passphrase` is called if the store is encrypted.
+pub fn get_registry_api_token(
+    move_home: &str,
+    registry: Option<&str>,
+    passphrase: &dyn Fn() -> Result<String>,
+) -> Result<String> {
+    Ok(get_registry_credential(move_home, registry, passphrase)?
+        .credential
+        .token)
+}
 
-pub fn get_registry_api_token(move_home: &str) -> Result<String> {
-    if let Ok(content) = get_api_token(move_home) {
-        Ok(content)
-    } else {
-        bail!(
+/// Like [`get_registry_api_token`], with what is known about the token.
+pub fn get_registry_credential(
+    move_home: &str,
+    registry: Option<&str>,
+    passphrase: &dyn Fn() -> Result<String>,
+) -> Result<RegistryCredential> {
+    // the credential file is not needed with a token in the environment,
+    // even to be valid
+    let name = registry_name(move_home, registry)
+        .unwrap_or_else(|_| registry.unwrap_or(DEFAULT_REGISTRY).to_string());
+    if let Some(token) = get_env_api_token(&name) {
+        return Ok(RegistryCredential {
+            name,
+            credential: Credential::new(token),
+            store: None,
+        });
+    }
+    stored_credential(move_home, registry, passphrase).with_context(|| {
+        format!(
             "There seems to be an error with your Movey API token. \
-            Please run `move movey-login` and follow the instructions."
+            Please run `move movey-login` and follow the instructions, \
+            or set the {} environment variable.",
+            registry_token_env(&name)
         )
+    })
+}
+
+/// Environment variable holding the token of the registry `name`:
+/// `MOVEY_TOKEN` for Movey, `MOVEY_REGISTRIES_<NAME>_TOKEN` for the others,
+/// with the name in upper case and `_` in place of other characters, so that
+/// a token is never sent to a registry it was not meant for.
+pub fn registry_token_env(name: &str) -> String {
+    if name == DEFAULT_REGISTRY {
+        return String::from(MOVEY_TOKEN_ENV);
     }
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("MOVEY_REGISTRIES_{}_TOKEN", name)
 }
 
-pub fn get_api_token(move_home: &str) -> Result<String> {
+/// The token in the environment variable of the registry `name`, if it is
+/// set and not blank.
+pub fn get_env_api_token(name: &str) -> Option<String> {
+    let token = std::env::var(registry_token_env(name)).ok()?;
+    let token = token.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// The token of `registry` in the credential store.
+pub fn get_api_token(
+    move_home: &str,
+    registry: Option<&str>,
+    passphrase: &dyn Fn() -> Result<String>,
+) -> Result<String> {
+    Ok(stored_credential(move_home, registry, passphrase)?
+        .credential
+        .token)
+}
+
+fn stored_credential(
+    move_home: &str,
+    registry: Option<&str>,
+    passphrase: &dyn Fn() -> Result<String>,
+) -> Result<RegistryCredential> {
+    let name = registry_name(move_home, registry)?;
+    let store = open_credential_store(move_home, passphrase)?;
+    match store.get(&name)? {
+        Some(credential) if !credential.token.is_empty() => Ok(RegistryCredential {
+            name,
+            credential,
+            store: Some(store),
+        }),
+        _ => bail!("No token for registry `{}` in {}", name, store.describe()),
+    }
+}
+
+/// Like [`resolve_registry`], for the credential file of `move_home` which
+/// may not exist.
+pub fn registry_name(move_home: &str, registry: Option<&str>) -> Result<String> {
     let credential_path = format!("{}{}", move_home, MOVEY_CREDENTIAL_PATH);
-    let mut toml: Value = read_credential_file(&credential_path)?;
-    let token = get_registry_field(&mut toml, "token")?;
-    Ok(token.to_string().replace('\"', ""))
+    if !Path::new(&credential_path).exists() {
+        return Ok(registry.unwrap_or(DEFAULT_REGISTRY).to_string());
+    }
+    Ok(resolve_registry(
+        &read_credential_file(&credential_path)?,
+        registry,
+    ))
 }
 
-pub fn get_movey_url(move_home: &str) -> Result<String> {
+/// Where the url of a registry was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlSource {
+    Flag,
+    Env,
+    Credential(PathBuf),
+    Project(PathBuf),
+    Default,
+}
+
+impl fmt::Display for UrlSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlSource::Flag => write!(f, "--registry-url flag"),
+            UrlSource::Env => write!(f, "{} environment variable", MOVEY_REGISTRY_URL_ENV),
+            UrlSource::Credential(path) => write!(f, "credential file {}", path.display()),
+            UrlSource::Project(path) => write!(f, "project config {}", path.display()),
+            UrlSource::Default => write!(f, "built-in default"),
+        }
+    }
+}
+
+/// The url of a registry and where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryUrl {
+    /// Name of the registry, see [`resolve_registry`].
+    pub name: String,
+    pub url: String,
+    pub source: UrlSource,
+}
+
+/// The url of `registry`, or of the default registry when it is `None`,
+/// taken from the first of:
+///
+/// 1. `flag`, the value of `--registry-url`
+/// 2. the `MOVEY_REGISTRY_URL` environment variable
+/// 3. the table of the registry in the credential file
+/// 4. the table of the registry in the closest `Movey.toml` from `project_dir`
+/// 5. [`MOVEY_URL`] for Movey, other registries must have a url
+pub fn resolve_movey_url(
+    move_home: &str,
+    registry: Option<&str>,
+    flag: Option<&str>,
+    project_dir: Option<&Path>,
+) -> Result<RegistryUrl> {
     let credential_path = format!("{}{}", move_home, MOVEY_CREDENTIAL_PATH);
-    let contents = fs::read_to_string(&credential_path)?;
-    let mut toml: Value = contents.parse()?;
+    // a credential file that cannot be read is reported with the token
+    let credential = match fs::read_to_string(&credential_path) {
+        Ok(_) => Some(read_credential_file(&credential_path)?),
+        Err(_) => None,
+    };
+    let name = match &credential {
+        Some(toml) => resolve_registry(toml, registry),
+        None => registry.unwrap_or(DEFAULT_REGISTRY).to_string(),
+    };
+    let found = |url: String, source: UrlSource| {
+        Ok(RegistryUrl {
+            name: name.clone(),
+            url,
+            source,
+        })
+    };
+
+    if let Some(url) = flag {
+        return found(url.to_string(), UrlSource::Flag);
+    }
+    if let Some(url) = env::var(MOVEY_REGISTRY_URL_ENV)
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+    {
+        return found(url.trim().to_string(), UrlSource::Env);
+    }
+    if let Some(url) = credential
+        .as_ref()
+        .and_then(|toml| registry_url(toml, &name))
+    {
+        return found(url, UrlSource::Credential(PathBuf::from(&credential_path)));
+    }
+    if let Some(config_path) = project_dir.and_then(find_project_config) {
+        let toml = read_credential_file(&config_path.to_string_lossy())?;
+        if let Some(url) = registry_url(&toml, &name) {
+            return found(url, UrlSource::Project(config_path));
+        }
+    }
+    if name == DEFAULT_REGISTRY {
+        return found(MOVEY_URL.to_string(), UrlSource::Default);
+    }
+    bail!(
+        "No url is configured for registry `{}`, add it to the [{}.{}] table of {}",
+        name,
+        REGISTRIES_TABLE,
+        name,
+        credential_path
+    )
+}
+
+/// Like [`resolve_movey_url`], for sending a token of the credential store.
+/// A project `Movey.toml` comes with the project and not from the user, so
+/// its url is refused: the token would go wherever the project says. The url
+/// must then be given with the flag, the environment variable or the
+/// credential file.
+pub fn resolve_token_url(
+    move_home: &str,
+    registry: Option<&str>,
+    flag: Option<&str>,
+    project_dir: Option<&Path>,
+) -> Result<RegistryUrl> {
+    let registry_url = resolve_movey_url(move_home, registry, flag, project_dir)?;
+    if let UrlSource::Project(config_path) = &registry_url.source {
+        let table = if registry_url.name == DEFAULT_REGISTRY {
+            String::from("registry")
+        } else {
+            format!("{}.{}", REGISTRIES_TABLE, registry_url.name)
+        };
+        bail!(
+            "The url {} of registry `{}` comes from {}, which is not trusted with \
+            the saved token. Pass it with --registry-url or {}, or add it to the \
+            [{}] table of {}{}",
+            registry_url.url,
+            registry_url.name,
+            config_path.display(),
+            MOVEY_REGISTRY_URL_ENV,
+            table,
+            move_home,
+            MOVEY_CREDENTIAL_PATH
+        )
+    }
+    Ok(registry_url)
+}
+
+/// Like [`resolve_movey_url`] without flag, from the working directory.
+pub fn get_movey_url(move_home: &str, registry: Option<&str>) -> Result<String> {
+    let project_dir = env::current_dir().ok();
+    Ok(resolve_movey_url(move_home, registry, None, project_dir.as_deref())?.url)
+}
+
+/// The closest `Movey.toml` in `dir` or its parents.
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+fn registry_url(toml: &Value, name: &str) -> Option<String> {
+    let url = registry_table(toml, name)?.get("url")?.as_str()?;
+    Some(url.to_string())
+}
 
-    let movey_url = get_registry_field(&mut toml, "url");
-    if let Ok(url) = movey_url {
-        Ok(url.to_string().replace('\"', ""))
+/// Name of the registry to use: `registry` if it is given, else the one
+/// named by `registry.default`, else Movey.
+pub fn resolve_registry(toml: &Value, registry: Option<&str>) -> String {
+    let default = || {
+        toml.get("registry")?
+            .get(DEFAULT_KEY)?
+            .as_str()
+            .map(str::to_string)
+    };
+    match registry {
+        Some(name) => name.to_string(),
+        None => default().unwrap_or_else(|| String::from(DEFAULT_REGISTRY)),
+    }
+}
+
+/// The table holding the credentials of the registry `name`. Movey keeps
+/// using the `[registry]` table, so existing credential files still work.
+pub fn registry_table<'a>(toml: &'a Value, name: &str) -> Option<&'a Map<String, Value>> {
+    if name == DEFAULT_REGISTRY {
+        toml.get("registry")?.as_table()
     } else {
-        Ok(MOVEY_URL.to_string())
+        toml.get(REGISTRIES_TABLE)?.get(name)?.as_table()
     }
 }
 
-fn get_registry_field<'a>(toml: &'a mut Value, field: &'a str) -> Result<&'a mut Value> {
-    let registry = toml
+/// Like [`registry_table`], creating the missing tables.
+pub fn registry_table_mut<'a>(
+    toml: &'a mut Value,
+    name: &str,
+) -> Result<&'a mut Map<String, Value>> {
+    let root = toml
         .as_table_mut()
-        .context(format!("Error parsing {}", MOVEY_CREDENTIAL_PATH))?
-        .get_mut("registry")
         .context(format!("Error parsing {}", MOVEY_CREDENTIAL_PATH))?;
-    let value = registry
+    let table = if name == DEFAULT_REGISTRY {
+        root.entry("registry")
+    } else {
+        root.entry(REGISTRIES_TABLE)
+            .or_insert_with(|| Value::Table(Map::new()))
+            .as_table_mut()
+            .context(format!("Error parsing {} table", REGISTRIES_TABLE))?
+            .entry(name)
+    };
+    table
+        .or_insert_with(|| Value::Table(Map::new()))
         .as_table_mut()
-        .context("Error parsing registry table")?
-        .get_mut(field)
-        .context("Error parsing token")?;
-    Ok(value)
+        .context(format!("Error parsing table of registry `{}`", name))
+}
+
+/// Names of the registries of the credential file.
+pub fn registry_names(toml: &Value) -> Vec<String> {
+    let mut names = vec![];
+    if registry_table(toml, DEFAULT_REGISTRY).is_some() {
+        names.push(String::from(DEFAULT_REGISTRY));
+    }
+    if let Some(registries) = toml.get(REGISTRIES_TABLE).and_then(Value::as_table) {
+        names.extend(registries.keys().cloned());
+    }
+    names
 }
 
 pub fn read_credential_file(credential_path: &str) -> Result<Value> {
@@ -73,6 +375,7 @@ pub fn read_credential_file(credential_path: &str) -> Result<Value> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::credential_store::env_passphrase;
     use std::{env, fs::File};
 
     fn setup_move_home(test_path: &str) -> (String, String) {
@@ -101,7 +404,7 @@ mod tests {
             "#;
         fs::write(&credential_path, content).unwrap();
 
-        let token = get_registry_api_token(&move_home).unwrap();
+        let token = get_registry_api_token(&move_home, None, &env_passphrase).unwrap();
         assert!(token.contains("test-token"));
 
         clean_up(&move_home)
@@ -113,7 +416,7 @@ mod tests {
         let (move_home, _) = setup_move_home(&test_path);
         let _ = fs::remove_dir_all(&move_home);
 
-        let token = get_registry_api_token(&move_home);
+        let token = get_registry_api_token(&move_home, None, &env_passphrase);
         assert!(token.is_err());
 
         clean_up(&move_home)
@@ -126,7 +429,7 @@ mod tests {
         let _ = fs::remove_dir_all(&move_home);
         fs::create_dir_all(&move_home).unwrap();
 
-        let token = get_registry_api_token(&move_home);
+        let token = get_registry_api_token(&move_home, None, &env_passphrase);
         assert!(token.is_err());
 
         clean_up(&move_home)
@@ -145,7 +448,7 @@ mod tests {
             token = test-token
             "#;
         fs::write(&credential_path, missing_double_quote).unwrap();
-        let token = get_registry_api_token(&move_home);
+        let token = get_registry_api_token(&move_home, None, &env_passphrase);
         assert!(token.is_err());
 
         let wrong_token_field = r#"
@@ -153,7 +456,7 @@ mod tests {
             tokens = "test-token"
             "#;
         fs::write(&credential_path, wrong_token_field).unwrap();
-        let token = get_registry_api_token(&move_home);
+        let token = get_registry_api_token(&move_home, None, &env_passphrase);
         assert!(token.is_err());
 
         clean_up(&move_home)
@@ -172,7 +475,7 @@ mod tests {
             "#;
         fs::write(&credential_path, content).unwrap();
 
-        let url = get_movey_url(&move_home).unwrap();
+        let url = get_movey_url(&move_home, None).unwrap();
         assert_eq!(url, "test-url");
 
         clean_up(&move_home)
@@ -190,9 +493,162 @@ mod tests {
             "#;
         fs::write(&credential_path, content).unwrap();
 
-        let url = get_movey_url(&move_home).unwrap();
+        let url = get_movey_url(&move_home, None).unwrap();
         assert_eq!(url, MOVEY_URL);
 
         clean_up(&move_home)
     }
+
+    #[test]
+    fn named_registries_work() {
+        let test_path = String::from("/named_registries_work");
+        let (move_home, credential_path) = setup_move_home(&test_path);
+        let _ = fs::create_dir_all(&move_home);
+        let content = r#"
+            [registry]
+            token = "movey-token"
+
+            [registries.staging]
+            token = "staging-token"
+            url = "staging-url"
+
+            [registries.local]
+            token = "local-token"
+            "#;
+        fs::write(&credential_path, content).unwrap();
+
+        let token = get_registry_api_token(&move_home, Some("staging"), &env_passphrase).unwrap();
+        assert_eq!(token, "staging-token");
+        let url = get_movey_url(&move_home, Some("staging")).unwrap();
+        assert_eq!(url, "staging-url");
+        let token = get_registry_api_token(&move_home, None, &env_passphrase).unwrap();
+        assert_eq!(token, "movey-token");
+        let url = get_movey_url(&move_home, Some(DEFAULT_REGISTRY)).unwrap();
+        assert_eq!(url, MOVEY_URL);
+
+        let error = get_movey_url(&move_home, Some("local")).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("No url is configured for registry `local`"));
+        assert!(get_registry_api_token(&move_home, Some("unknown"), &env_passphrase).is_err());
+
+        clean_up(&move_home)
+    }
+
+    #[test]
+    fn default_key_selects_the_registry() {
+        let toml: Value = r#"
+            [registry]
+            default = "staging"
+            token = "movey-token"
+
+            [registries.staging]
+            token = "staging-token"
+            "#
+        .parse()
+        .unwrap();
+        assert_eq!(resolve_registry(&toml, None), "staging");
+        assert_eq!(resolve_registry(&toml, Some("movey")), "movey");
+        assert_eq!(registry_names(&toml), ["movey", "staging"]);
+
+        let toml: Value = "[registry]\ntoken = \"movey-token\"\n".parse().unwrap();
+        assert_eq!(resolve_registry(&toml, None), DEFAULT_REGISTRY);
+    }
+
+    #[test]
+    fn registry_token_env_is_scoped_to_the_registry() {
+        assert_eq!(registry_token_env(DEFAULT_REGISTRY), "MOVEY_TOKEN");
+        assert_eq!(
+            registry_token_env("staging"),
+            "MOVEY_REGISTRIES_STAGING_TOKEN"
+        );
+        assert_eq!(
+            registry_token_env("my-registry.2"),
+            "MOVEY_REGISTRIES_MY_REGISTRY_2_TOKEN"
+        );
+    }
+
+    #[test]
+    fn registry_table_mut_creates_missing_tables() {
+        let mut toml = Value::Table(Map::new());
+        registry_table_mut(&mut toml, "staging")
+            .unwrap()
+            .insert(String::from("token"), Value::from("staging-token"));
+        registry_table_mut(&mut toml, DEFAULT_REGISTRY).unwrap();
+
+        let staging = registry_table(&toml, "staging").unwrap();
+        assert_eq!(staging["token"].as_str(), Some("staging-token"));
+        assert!(registry_table(&toml, DEFAULT_REGISTRY).unwrap().is_empty());
+    }
+
+    #[test]
+    fn resolve_movey_url_follows_the_documented_order() {
+        let test_path = String::from("/resolve_movey_url_follows_the_documented_order");
+        let (move_home, credential_path) = setup_move_home(&test_path);
+        let project_dir = PathBuf::from(&move_home).join("project/package");
+        let _ = fs::remove_dir_all(&move_home);
+        fs::create_dir_all(&project_dir).unwrap();
+        let config_path = PathBuf::from(&move_home)
+            .join("project")
+            .join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &config_path,
+            "[registry]\nurl = \"project-url\"\n\n[registries.staging]\nurl = \"project-staging-url\"\n",
+        )
+        .unwrap();
+
+        let resolve = |registry, flag| {
+            resolve_movey_url(&move_home, registry, flag, Some(&project_dir)).unwrap()
+        };
+        let url = resolve(None, Some("flag-url"));
+        assert_eq!(
+            (url.url.as_str(), url.source),
+            ("flag-url", UrlSource::Flag)
+        );
+        let url = resolve(None, None);
+        let config_path = config_path.canonicalize().unwrap();
+        assert_eq!(url.url, "project-url");
+        assert_eq!(url.source, UrlSource::Project(config_path.clone()));
+
+        fs::write(&credential_path, "[registry]\nurl = \"credential-url\"\n").unwrap();
+        let url = resolve(None, None);
+        assert_eq!(url.url, "credential-url");
+        assert_eq!(
+            url.source,
+            UrlSource::Credential(PathBuf::from(&credential_path))
+        );
+        let url = resolve(Some("staging"), None);
+        assert_eq!(url.url, "project-staging-url");
+        assert_eq!(url.source, UrlSource::Project(config_path));
+        let error = resolve_token_url(&move_home, Some("staging"), None, Some(&project_dir))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("not trusted with the saved token"),
+            "{}",
+            error
+        );
+        let url = resolve_token_url(
+            &move_home,
+            Some("staging"),
+            Some("flag-url"),
+            Some(&project_dir),
+        )
+        .unwrap();
+        assert_eq!(url.source, UrlSource::Flag);
+        let url = resolve_token_url(&move_home, None, None, Some(&project_dir)).unwrap();
+        assert_eq!(url.url, "credential-url");
+
+        let url = resolve_movey_url(&move_home, None, None, None).unwrap();
+        assert_eq!(url.url, "credential-url");
+        fs::remove_file(&credential_path).unwrap();
+        let url = resolve_movey_url(&move_home, None, None, None).unwrap();
+        assert_eq!(
+            (url.url.as_str(), url.source),
+            (MOVEY_URL, UrlSource::Default)
+        );
+        assert!(resolve_movey_url(&move_home, Some("staging"), None, None).is_err());
+
+        clean_up(&move_home)
+    }
 }
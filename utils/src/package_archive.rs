@@ -0,0 +1,285 @@
+// Copyright (c) The Move Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Context, Result};
+use flate2::{write::GzEncoder, Compression, GzBuilder};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, Metadata},
+    path::{Component, Path, PathBuf},
+};
+use tar::{EntryType, Header};
+
+// Every entry gets the same modification time so that archives of the same
+// files are byte-for-byte identical
+const ARCHIVE_MTIME: u64 = 1_153_704_088;
+
+/// A gzipped tarball of the source files of a package.
+///
+/// Entries are sorted, placed under a `<name>-<version>/` directory and have
+/// fixed timestamps, owners and permissions, so the same files always
+/// produce the same archive and checksum.
+pub struct PackageArchive {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+    pub sha256: String,
+    /// Number of files in the archive.
+    pub total_files: usize,
+}
+
+impl PackageArchive {
+    /// Build the archive of `files`, given relative to `package_root`.
+    /// Files that no longer exist in the working tree are left out, and
+    /// symbolic links are archived as links, which must point inside the
+    /// package.
+    pub fn build(
+        package_root: &Path,
+        files: &[PathBuf],
+        name: &str,
+        version: &str,
+    ) -> Result<PackageArchive> {
+        let base_dir = format!("{}-{}", name, version);
+        let mut files = files.to_vec();
+        files.sort();
+        files.dedup();
+
+        let encoder = GzBuilder::new()
+            .mtime(0)
+            .write(vec![], Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut total_files = 0;
+        for file in &files {
+            let path = package_root.join(file);
+            // links are not followed, so that a link cannot publish a file
+            // from outside of the package
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let mut header = Header::new_gnu();
+            header.set_mtime(ARCHIVE_MTIME);
+            header.set_uid(0);
+            header.set_gid(0);
+            let archive_path = Path::new(&base_dir).join(file);
+            if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&path)
+                    .with_context(|| format!("could not read {}", path.display()))?;
+                if !link_stays_inside(file, &target) {
+                    bail!(
+                        "{} is a symbolic link to {}, which is outside of the package",
+                        file.display(),
+                        target.display()
+                    )
+                }
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                header.set_mode(0o777);
+                builder.append_link(&mut header, archive_path, &target)?;
+            } else if metadata.is_file() {
+                let content = fs::read(&path)
+                    .with_context(|| format!("could not read {}", path.display()))?;
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(content.len() as u64);
+                header.set_mode(normalized_mode(&metadata));
+                builder.append_data(&mut header, archive_path, content.as_slice())?;
+            } else {
+                continue;
+            }
+            total_files += 1;
+        }
+        let bytes = finish(builder)?;
+        let sha256 = format!("{:x}", Sha256::digest(&bytes));
+
+        Ok(PackageArchive {
+            file_name: format!("{}.tar.gz", base_dir),
+            bytes,
+            sha256,
+            total_files,
+        })
+    }
+
+    /// Write the archive into `output_dir`, creating it if needed, and return
+    /// the path of the written file.
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(output_dir)?;
+        let path = output_dir.join(&self.file_name);
+        fs::write(&path, &self.bytes)
+            .with_context(|| format!("could not write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+fn finish(builder: tar::Builder<GzEncoder<Vec<u8>>>) -> Result<Vec<u8>> {
+    Ok(builder.into_inner()?.finish()?)
+}
+
+// whether the link at `file`, relative to the package root, to `target`
+// resolves inside the package, without looking at the files on the way
+fn link_stays_inside(file: &Path, target: &Path) -> bool {
+    let mut depth = file.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+// only the executable bit is kept from the working tree
+#[cfg(unix)]
+fn normalized_mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode();
+    if mode & 0o111 != 0 {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(not(unix))]
+fn normalized_mode(_metadata: &Metadata) -> u32 {
+    0o644
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use tar::Archive;
+
+    fn write_file(root: &Path, path: &str, content: &str) -> PathBuf {
+        let full_path = root.join(path);
+        fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        fs::write(&full_path, content).unwrap();
+        PathBuf::from(path)
+    }
+
+    #[test]
+    fn build_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            write_file(dir.path(), "sources/B.move", "module 0x1::B {}"),
+            write_file(dir.path(), "Move.toml", "[package]"),
+            write_file(dir.path(), "sources/A.move", "module 0x1::A {}"),
+            PathBuf::from("sources/Deleted.move"),
+        ];
+        write_file(dir.path(), "untracked.move", "");
+
+        let archive = PackageArchive::build(dir.path(), &files, "Package1", "0.1.0").unwrap();
+        assert_eq!(archive.file_name, "Package1-0.1.0.tar.gz");
+        assert_eq!(archive.sha256.len(), 64);
+        assert_eq!(archive.total_files, 3);
+
+        let mut entries = vec![];
+        let mut tar = Archive::new(GzDecoder::new(archive.bytes.as_slice()));
+        for entry in tar.entries().unwrap() {
+            let entry = entry.unwrap();
+            let header = entry.header();
+            assert_eq!(header.mtime().unwrap(), ARCHIVE_MTIME);
+            assert_eq!(header.mode().unwrap(), 0o644);
+            assert_eq!(header.uid().unwrap(), 0);
+            entries.push(entry.path().unwrap().to_path_buf());
+        }
+        assert_eq!(
+            entries,
+            vec![
+                PathBuf::from("Package1-0.1.0/Move.toml"),
+                PathBuf::from("Package1-0.1.0/sources/A.move"),
+                PathBuf::from("Package1-0.1.0/sources/B.move"),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_is_deterministic() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let files = vec![
+            write_file(first.path(), "Move.toml", "[package]"),
+            write_file(first.path(), "sources/A.move", "module 0x1::A {}"),
+        ];
+        // written in a different order, at a different time
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        write_file(second.path(), "sources/A.move", "module 0x1::A {}");
+        write_file(second.path(), "Move.toml", "[package]");
+
+        let first_archive = PackageArchive::build(first.path(), &files, "P", "0.0.0").unwrap();
+        let reversed: Vec<PathBuf> = files.iter().rev().cloned().collect();
+        let second_archive = PackageArchive::build(second.path(), &reversed, "P", "0.0.0").unwrap();
+        assert_eq!(first_archive.bytes, second_archive.bytes);
+        assert_eq!(first_archive.sha256, second_archive.sha256);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_normalizes_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = write_file(dir.path(), "run.sh", "#!/bin/sh");
+        let private = write_file(dir.path(), "private.move", "");
+        fs::set_permissions(dir.path().join(&script), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(dir.path().join(&private), fs::Permissions::from_mode(0o600)).unwrap();
+
+        let archive = PackageArchive::build(dir.path(), &[script, private], "P", "0.0.0").unwrap();
+        let mut tar = Archive::new(GzDecoder::new(archive.bytes.as_slice()));
+        let modes: Vec<u32> = tar
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().header().mode().unwrap())
+            .collect();
+        assert_eq!(modes, vec![0o644, 0o755]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_archives_links_inside_the_package_only() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let secret = tempfile::NamedTempFile::new().unwrap();
+        write_file(dir.path(), "sources/A.move", "module 0x1::A {}");
+        symlink("A.move", dir.path().join("sources/Link.move")).unwrap();
+        symlink("../../outside", dir.path().join("sources/Up.move")).unwrap();
+        symlink(secret.path(), dir.path().join("Secret.move")).unwrap();
+
+        let archive = PackageArchive::build(
+            dir.path(),
+            &[PathBuf::from("sources/Link.move")],
+            "P",
+            "0.0.0",
+        )
+        .unwrap();
+        assert_eq!(archive.total_files, 1);
+        let mut tar = Archive::new(GzDecoder::new(archive.bytes.as_slice()));
+        let entry = tar.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.header().entry_type(), EntryType::Symlink);
+        assert_eq!(entry.link_name().unwrap().unwrap(), Path::new("A.move"));
+
+        for file in ["sources/Up.move", "Secret.move"] {
+            let error = PackageArchive::build(dir.path(), &[PathBuf::from(file)], "P", "0.0.0")
+                .err()
+                .unwrap();
+            assert!(
+                error.to_string().contains("outside of the package"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn write_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![write_file(dir.path(), "Move.toml", "[package]")];
+        let archive = PackageArchive::build(dir.path(), &files, "P", "0.0.0").unwrap();
+
+        let path = archive.write(&dir.path().join("build")).unwrap();
+        assert_eq!(path, dir.path().join("build/P-0.0.0.tar.gz"));
+        assert_eq!(fs::read(path).unwrap(), archive.bytes);
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::Context;
+use std::io;
+use utils::error::Error;
+
+#[test]
+fn report_prints_message_of_command_error_with_its_causes() {
+    let error: Error = Err::<(), _>(io::Error::new(io::ErrorKind::NotFound, "no such file"))
+        .context("could not read Move.toml")
+        .context("invalid package in packages/base/Move.toml")
+        .unwrap_err()
+        .into();
+
+    assert_eq!(
+        error.report(false),
+        "invalid package in packages/base/Move.toml\n\n\
+        Caused by:\n    \
+        0: could not read Move.toml\n    \
+        1: no such file"
+    );
+}
+
+#[test]
+fn report_indents_causes_spanning_several_lines() {
+    let error: Error = anyhow::anyhow!("line 1\nline 2")
+        .context("could not parse Move.toml")
+        .into();
+
+    assert_eq!(
+        error.report(false),
+        "could not parse Move.toml\n\nCaused by:\n    line 1\n    line 2"
+    );
+}
+
+#[test]
+fn report_prints_message_of_other_errors_once() {
+    let error = Error::from(io::Error::other("disk full"));
+    assert_eq!(error.report(true), "disk full");
+    assert_eq!(
+        Error::Poison.report(false),
+        "the configuration lock is poisoned"
+    );
+}
@@ -0,0 +1,263 @@
+use git2::{IndexAddOption, Repository, Signature};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+use utils::git::{GitRemote, GitRepository};
+
+// create an empty repository in a fresh temporary directory
+fn init_repo() -> (TempDir, Repository) {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    (dir, repo)
+}
+
+fn write_file(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+// stage every file of the working tree, like `git add .`
+fn add_all(repo: &Repository) {
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+}
+
+fn commit_all(repo: &Repository) -> String {
+    add_all(repo);
+    let mut index = repo.index().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("Your Name", "you@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "commit",
+        &tree,
+        &parents,
+    )
+    .unwrap()
+    .to_string()
+}
+
+#[test]
+fn discover_fails_outside_a_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let error = GitRepository::discover(dir.path()).err().unwrap();
+    assert!(error.to_string().contains("invalid git repository"));
+}
+
+#[test]
+fn discover_fails_if_directory_does_not_exist() {
+    let (dir, _repo) = init_repo();
+    let error = GitRepository::discover(&dir.path().join("missing"))
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("invalid directory"));
+}
+
+#[test]
+fn prefix_is_empty_at_the_top_level() {
+    let (dir, _repo) = init_repo();
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert_eq!(repository.prefix(), Path::new(""));
+    assert_eq!(
+        repository.workdir().canonicalize().unwrap(),
+        dir.path().canonicalize().unwrap()
+    );
+}
+
+#[test]
+fn prefix_is_the_package_path_in_a_subdirectory() {
+    let (dir, _repo) = init_repo();
+    let package = dir.path().join("packages/foo");
+    fs::create_dir_all(&package).unwrap();
+
+    let repository = GitRepository::discover(&package).unwrap();
+    assert_eq!(repository.prefix(), Path::new("packages/foo"));
+}
+
+#[test]
+fn remotes_works() {
+    let (dir, repo) = init_repo();
+    repo.remote("origin", "git@gitlab.com:move/package.git")
+        .unwrap();
+    repo.remote("mirror", "https://gitea.example.com/move/package")
+        .unwrap();
+
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    let mut remotes = repository.remotes().unwrap();
+    remotes.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        remotes,
+        vec![
+            GitRemote {
+                name: String::from("mirror"),
+                url: String::from("https://gitea.example.com/move/package"),
+            },
+            GitRemote {
+                name: String::from("origin"),
+                url: String::from("git@gitlab.com:move/package.git"),
+            },
+        ]
+    );
+}
+
+#[test]
+fn remotes_is_empty_without_remotes() {
+    let (dir, _repo) = init_repo();
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert!(repository.remotes().unwrap().is_empty());
+}
+
+#[test]
+fn tracked_files_lists_staged_files_under_the_package() {
+    let (dir, repo) = init_repo();
+    write_file(dir.path(), "README.md", "");
+    write_file(dir.path(), "foo/Move.toml", "");
+    write_file(dir.path(), "foo/sources/Foo.move", "");
+    write_file(dir.path(), "foo/untracked.move", "");
+    write_file(dir.path(), "bar/Move.toml", "");
+    add_all(&repo);
+    write_file(dir.path(), "foo/new.move", "");
+
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert_eq!(repository.tracked_files().unwrap().len(), 5);
+
+    let repository = GitRepository::discover(&dir.path().join("foo")).unwrap();
+    assert_eq!(
+        repository.tracked_files().unwrap(),
+        vec![
+            PathBuf::from("Move.toml"),
+            PathBuf::from("sources/Foo.move"),
+            PathBuf::from("untracked.move"),
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn tracked_files_keeps_non_utf8_paths() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let (dir, repo) = init_repo();
+    let name = OsStr::from_bytes(b"caf\xe9.move");
+    fs::write(dir.path().join(name), "").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(name)).unwrap();
+    index.write().unwrap();
+
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert_eq!(
+        repository.tracked_files().unwrap(),
+        vec![PathBuf::from(name)]
+    );
+}
+
+#[test]
+fn head_commit_is_none_before_the_first_commit() {
+    let (dir, _repo) = init_repo();
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert!(repository.head_commit().unwrap().is_none());
+}
+
+#[test]
+fn head_commit_works() {
+    let (dir, repo) = init_repo();
+    write_file(dir.path(), "Move.toml", "");
+    commit_all(&repo);
+    write_file(dir.path(), "sources/Foo.move", "");
+    let commit = commit_all(&repo);
+
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert_eq!(repository.head_commit().unwrap(), Some(commit));
+}
+
+#[test]
+fn dirty_files_is_empty_for_a_clean_tree() {
+    let (dir, repo) = init_repo();
+    write_file(dir.path(), "Move.toml", "");
+    commit_all(&repo);
+
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert!(repository.dirty_files().unwrap().is_empty());
+    assert!(!repository.is_dirty().unwrap());
+}
+
+#[test]
+fn dirty_files_reports_changes_under_the_package_only() {
+    let (dir, repo) = init_repo();
+    write_file(dir.path(), ".gitignore", "build/\n");
+    write_file(dir.path(), "foo/Move.toml", "");
+    write_file(dir.path(), "foo/sources/Foo.move", "");
+    write_file(dir.path(), "bar/Move.toml", "");
+    commit_all(&repo);
+
+    write_file(dir.path(), "foo/Move.toml", "[package]");
+    fs::remove_file(dir.path().join("foo/sources/Foo.move")).unwrap();
+    write_file(dir.path(), "foo/sources/New.move", "");
+    write_file(dir.path(), "foo/build/Foo.mv", "");
+    write_file(dir.path(), "bar/Move.toml", "[package]");
+
+    let repository = GitRepository::discover(&dir.path().join("foo")).unwrap();
+    assert_eq!(
+        repository.dirty_files().unwrap(),
+        vec![
+            PathBuf::from("Move.toml"),
+            PathBuf::from("sources/Foo.move"),
+            PathBuf::from("sources/New.move"),
+        ]
+    );
+    assert!(repository.is_dirty().unwrap());
+
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert_eq!(repository.dirty_files().unwrap().len(), 4);
+}
+
+#[test]
+fn head_branch_works() {
+    let (dir, repo) = init_repo();
+    repo.set_head("refs/heads/release").unwrap();
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert_eq!(
+        repository.head_branch().unwrap().as_deref(),
+        Some("release")
+    );
+
+    write_file(dir.path(), "Move.toml", "");
+    let commit = commit_all(&repo);
+    repo.set_head_detached(git2::Oid::from_str(&commit).unwrap())
+        .unwrap();
+    assert!(repository.head_branch().unwrap().is_none());
+}
+
+#[test]
+fn head_tag_works() {
+    let (dir, repo) = init_repo();
+    let repository = GitRepository::discover(dir.path()).unwrap();
+    assert!(repository.head_tag().unwrap().is_none());
+
+    write_file(dir.path(), "Move.toml", "");
+    commit_all(&repo);
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let signature = Signature::now("Your Name", "you@example.com").unwrap();
+    repo.tag("v0.1.0", head.as_object(), &signature, "release", false)
+        .unwrap();
+    repo.tag_lightweight("a-lightweight", head.as_object(), false)
+        .unwrap();
+    assert_eq!(
+        repository.head_tag().unwrap().as_deref(),
+        Some("a-lightweight")
+    );
+
+    write_file(dir.path(), "sources/Foo.move", "");
+    commit_all(&repo);
+    assert!(repository.head_tag().unwrap().is_none());
+}